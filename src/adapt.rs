@@ -0,0 +1,173 @@
+//! Adapting colors picked for one kind of background to another.
+//!
+//! Most themes are designed against a dark background, and the same pale
+//! yellows and cyans that stand out on black all but disappear on white. The
+//! functions here keep a color's hue and chroma, and move its lightness to
+//! the other side of the actual background if need be, so that it has at
+//! least as much ([APCA]) contrast against it as it had against the
+//! background it was designed for. As APCA knows that dark text on a light
+//! background needs less of a lightness difference than the reverse, pale
+//! colors come out as mid-tones, rather than all turning into near-black.
+//!
+//! ```
+//! use termpal::adapt::{adapt_ansi256, Scheme};
+//!
+//! let warning = (0xff, 0xd7, 0x5f);
+//! // Unchanged on a black background (index 16)...
+//! assert_eq!(adapt_ansi256(warning, Scheme::Dark, 16), termpal::nearest_ansi256(0xff, 0xd7, 0x5f));
+//! // ...and a darker, still yellowish, color on a light one.
+//! let on_light = adapt_ansi256(warning, Scheme::Dark, (0xfd, 0xf6, 0xe3));
+//! let (r, g, b) = termpal::ansi256_to_rgb(on_light);
+//! assert!(r < 0xc0 && r >= g && g > b);
+//! ```
+//!
+//! The background can be given either as RGB (e.g. from
+//! [`query`](crate::query) when that's available), or as a palette index.
+//!
+//! [APCA]: https://github.com/Myndex/apca-w3
+use crate::color::OkLab;
+use crate::contrast::{sapc, wcag_ratio};
+use crate::Background;
+
+type Rgb = (u8, u8, u8);
+
+/// Whether a background is dark or light.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    Dark,
+    Light,
+}
+
+impl Scheme {
+    /// Classify `background`: it's dark if white text on it has more
+    /// (WCAG) contrast than black text does.
+    pub fn of(background: impl Into<Background>) -> Self {
+        let bg = background.into().to_rgb();
+        if wcag_ratio((0xff, 0xff, 0xff), bg) > wcag_ratio((0, 0, 0), bg) {
+            Scheme::Dark
+        } else {
+            Scheme::Light
+        }
+    }
+}
+
+/// Remap `color`, which was picked to go on a `designed_for` background
+/// (taken to be black for [`Scheme::Dark`], and white for
+/// [`Scheme::Light`]), to suit `background`.
+///
+/// If the color already has at least as much contrast against `background`
+/// as it had against that, it's left alone. Otherwise the result has the
+/// same hue and chroma (except where the chroma has to come down to stay in
+/// gamut), and the lightness closest to the original's that has enough
+/// contrast, or failing that, as much as it can get.
+pub fn adapt(color: Rgb, designed_for: Scheme, background: impl Into<Background>) -> OkLab {
+    let lab = OkLab::from_rgb(color);
+    let bg = background.into().to_rgb();
+    let target = match designed_for {
+        Scheme::Dark => sapc(color, (0, 0, 0)),
+        Scheme::Light => sapc(color, (0xff, 0xff, 0xff)),
+    }
+    .abs();
+
+    if sapc(color, bg).abs() >= target {
+        return lab;
+    }
+    let at = |l: f32| OkLab { l, ..lab }.clip_to_gamut();
+    let enough = |l: f32| sapc(at(l).to_rgb(), bg).abs() >= target;
+    // Contrast only goes up from the background's lightness to the far end,
+    // so bisect between the far end and whichever of the original and the
+    // background is further along.
+    let bg_l = OkLab::from_rgb(bg).l;
+    let (mut near, mut far) = match Scheme::of(bg) {
+        Scheme::Dark => (bg_l.max(lab.l), 1.0),
+        Scheme::Light => (bg_l.min(lab.l), 0.0),
+    };
+    if enough(far) {
+        for _ in 0..20 {
+            let mid = (near + far) / 2.0;
+            if enough(mid) {
+                far = mid;
+            } else {
+                near = mid;
+            }
+        }
+    }
+    at(far)
+}
+
+/// The same as [`adapt`], returning the nearest 256-color palette entry.
+#[inline]
+pub fn adapt_ansi256(color: Rgb, designed_for: Scheme, background: impl Into<Background>) -> u8 {
+    adapt(color, designed_for, background).nearest_ansi256()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::OkLch;
+    use crate::contrast::apca_lc;
+
+    #[test]
+    fn test_scheme() {
+        assert_eq!(Scheme::of(16), Scheme::Dark);
+        assert_eq!(Scheme::of(231), Scheme::Light);
+        assert_eq!(Scheme::of((0x1e, 0x1e, 0x2e)), Scheme::Dark);
+        assert_eq!(Scheme::of((0xfd, 0xf6, 0xe3)), Scheme::Light);
+        assert_eq!(Scheme::of((0x80, 0x80, 0x80)), Scheme::Light);
+    }
+
+    #[test]
+    fn test_adapt() {
+        let theme = [
+            (0xff, 0xd7, 0x5f),
+            (0x5f, 0xd7, 0xff),
+            (0xff, 0x5f, 0x5f),
+            (0x87, 0xd7, 0x87),
+        ];
+        for &c in &theme {
+            // Back onto the extreme it was designed for, nothing changes.
+            let same = adapt(c, Scheme::Dark, (0, 0, 0)).to_rgb();
+            assert_eq!(same, c);
+            let same = adapt(c, Scheme::Light, (0xff, 0xff, 0xff)).to_rgb();
+            assert_eq!(same, c);
+
+            // On white, it ends up with at least about as much contrast as
+            // it had on black.
+            let flipped = adapt(c, Scheme::Dark, (0xff, 0xff, 0xff));
+            let lab = OkLab::from_rgb(c);
+            let before = apca_lc(c, (0, 0, 0)).abs();
+            let after = apca_lc(flipped.to_rgb(), (0xff, 0xff, 0xff)).abs();
+            assert!(after > before - 1.0, "{c:?} {before} {after}");
+            if flipped != lab {
+                assert!(flipped.l < lab.l);
+                assert!(after < before + 1.0, "{c:?} {before} {after}");
+            }
+
+            // With the same hue.
+            let (h0, h1) = (OkLch::from(lab).h, OkLch::from(flipped).h);
+            let dh = (h0 - h1).abs();
+            assert!(dh.min(360.0 - dh) < 2.0, "{c:?} {h0} {h1}");
+        }
+
+        // A red that's fine on either is left alone.
+        let red = (0xff, 0x5f, 0x5f);
+        assert_eq!(adapt(red, Scheme::Dark, 231), OkLab::from_rgb(red));
+
+        // Pale colors come out as mid-tones, rather than near-black.
+        let yellow = adapt((0xff, 0xd7, 0x5f), Scheme::Dark, (0xff, 0xff, 0xff));
+        assert!(yellow.l > 0.35, "{yellow:?}");
+
+        // On a dark grey, dim colors are lifted to keep their contrast.
+        let dim = (0x60, 0x60, 0x80);
+        let bg = crate::ansi256_to_rgb(238);
+        let lifted = adapt(dim, Scheme::Dark, 238);
+        assert!(lifted.l > OkLab::from_rgb(dim).l);
+        let before = apca_lc(dim, (0, 0, 0));
+        let after = apca_lc(lifted.to_rgb(), bg);
+        assert!((after - before).abs() < 1.0, "{before} {after}");
+        assert_eq!(
+            adapt_ansi256(dim, Scheme::Dark, 238),
+            lifted.nearest_ansi256()
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! `termpal`: rewrite the colors in terminal output read from stdin.
+//!
+//! This is a thin wrapper around [`termpal::rewrite::Downgrader`], for piping
+//! the output of programs that assume true color support through, e.g.
+//!
+//! ```text
+//! bat --color=always src/lib.rs | termpal --depth 256 | less -R
+//! ```
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use termpal::rewrite::{Downgrader, Mode};
+use termpal::ColorDepth;
+
+const USAGE: &str = "\
+usage: termpal [--depth 256|88|16 | --strip | --upgrade]
+
+Reads terminal output from stdin, and writes it to stdout with the colors in
+its SGR escape sequences rewritten. Everything else is passed through as-is.
+
+options:
+    --depth N    convert true colors (and indexed ones, if needed) to the
+                 nearest colors in a 256, 88 or 16 color palette [default: 256]
+    --strip      remove all colors, keeping other attributes like bold
+    --upgrade    replace indexed colors with the equivalent true colors
+    -h, --help   print this message
+";
+
+fn parse_depth(s: &str) -> Result<ColorDepth, String> {
+    match s {
+        "256" => Ok(ColorDepth::Ansi256),
+        #[cfg(feature = "88color")]
+        "88" => Ok(ColorDepth::Ansi88),
+        #[cfg(not(feature = "88color"))]
+        "88" => Err("88 color support wasn't enabled when termpal was built \
+             (it needs the `88color` feature)"
+            .into()),
+        "16" => Ok(ColorDepth::Ansi16),
+        _ => Err(format!("unknown depth `{s}` (expected 256, 88 or 16)")),
+    }
+}
+
+/// Returns `None` if `--help` was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Mode>, String> {
+    let mut mode = None;
+    let mut set = |m: Mode| match mode.replace(m) {
+        Some(_) => Err("only one of --depth, --strip and --upgrade can be used".to_string()),
+        None => Ok(()),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--strip" => set(Mode::Strip)?,
+            "--upgrade" => set(Mode::Upgrade)?,
+            "--depth" => {
+                let depth = args.next().ok_or("--depth needs a value")?;
+                set(Mode::Downgrade(parse_depth(&depth)?))?;
+            }
+            _ => match arg.strip_prefix("--depth=") {
+                Some(depth) => set(Mode::Downgrade(parse_depth(depth)?))?,
+                None => return Err(format!("unknown argument `{arg}`")),
+            },
+        }
+    }
+    Ok(Some(mode.unwrap_or(ColorDepth::Ansi256.into())))
+}
+
+fn run(mode: Mode) -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
+    let mut out = Downgrader::new(io::stdout().lock(), mode);
+    let mut buf = vec![0; 1 << 14];
+    loop {
+        let n = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.write_all(&buf[..n])?;
+        // Flush every read, so that interactive output isn't held up.
+        out.flush()?;
+    }
+    out.finish()?;
+    out.flush()
+}
+
+fn main() -> ExitCode {
+    let mode = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(mode)) => mode,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprint!("termpal: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(mode) {
+        // Whatever we were piping into went away (e.g. `| head`), which isn't
+        // worth complaining about.
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("termpal: {e}");
+            ExitCode::FAILURE
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Mode>, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_args() {
+        assert_eq!(parse(&[]), Ok(Some(Mode::Downgrade(ColorDepth::Ansi256))));
+        assert_eq!(
+            parse(&["--depth", "16"]),
+            Ok(Some(Mode::Downgrade(ColorDepth::Ansi16)))
+        );
+        assert_eq!(
+            parse(&["--depth=256"]),
+            Ok(Some(Mode::Downgrade(ColorDepth::Ansi256)))
+        );
+        #[cfg(feature = "88color")]
+        assert_eq!(
+            parse(&["--depth", "88"]),
+            Ok(Some(Mode::Downgrade(ColorDepth::Ansi88)))
+        );
+        #[cfg(not(feature = "88color"))]
+        assert!(parse(&["--depth", "88"]).is_err());
+        assert_eq!(parse(&["--strip"]), Ok(Some(Mode::Strip)));
+        assert_eq!(parse(&["--upgrade"]), Ok(Some(Mode::Upgrade)));
+        assert_eq!(parse(&["--strip", "-h"]), Ok(None));
+        assert_eq!(parse(&["--help"]), Ok(None));
+    }
+
+    #[test]
+    fn test_bad_args() {
+        assert!(parse(&["--depth"]).unwrap_err().contains("needs a value"));
+        assert!(parse(&["--depth", "8"])
+            .unwrap_err()
+            .contains("unknown depth"));
+        assert!(parse(&["--depth="]).unwrap_err().contains("unknown depth"));
+        assert!(parse(&["--strip", "--upgrade"])
+            .unwrap_err()
+            .contains("only one of"));
+        assert!(parse(&["--depth", "16", "--strip"]).is_err());
+        assert!(parse(&["--bogus"])
+            .unwrap_err()
+            .contains("unknown argument"));
+    }
+}
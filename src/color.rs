@@ -0,0 +1,394 @@
+//! Manipulating colors in Oklab and OkLCh, then quantizing the result.
+//!
+//! This covers the usual "10% lighter", "less saturated" or "30% of the way
+//! from A to B" adjustments, done in [Oklab] (so that they look even), along
+//! with variants that go straight to the nearest 256-color palette index.
+//!
+//! ```
+//! use termpal::color::{self, OkLab};
+//!
+//! let accent = (0x3d, 0x7e, 0xd6);
+//! let hover = OkLab::from_rgb(accent).lighten(0.1).to_rgb();
+//! assert!(hover.2 > accent.2);
+//!
+//! // Or directly as palette indices.
+//! let border = color::mix_ansi256(accent, (0x1e, 0x1e, 0x1e), 0.7);
+//! let muted = color::saturate_ansi256(accent, -0.5);
+//! # let _ = (border, muted);
+//! ```
+//!
+//! Results that fall outside the sRGB gamut (which is easy to do by
+//! saturating or lightening) are brought back in by reducing chroma, keeping
+//! the lightness and hue, rather than by clamping each channel (which shifts
+//! the hue).
+//!
+//! [Oklab]: https://bottosson.github.io/posts/oklab
+use crate::imp::oklab::sqrt;
+use core::f64::consts::PI;
+
+pub use crate::imp::oklab::OkLab;
+
+type Rgb = (u8, u8, u8);
+
+/// A color in OkLCh: Oklab in polar form.
+///
+/// `l` is the same as [`OkLab::l`], from 0 (black) to 1 (white), `c` is the
+/// chroma (0 for greys, and up to about 0.37 within sRGB), and `h` is the hue
+/// angle in degrees, in `0.0..360.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OkLch {
+    /// Lightness, from 0 (black) to 1 (white).
+    pub l: f32,
+    /// Chroma, 0 for greys.
+    pub c: f32,
+    /// Hue angle in degrees, in `0.0..360.0`.
+    pub h: f32,
+}
+
+impl OkLab {
+    /// Convert from an sRGB color.
+    #[inline]
+    pub fn from_rgb((r, g, b): Rgb) -> Self {
+        Self::from_srgb8(r, g, b)
+    }
+
+    /// Convert to an sRGB color, reducing the chroma if it's out of gamut.
+    pub fn to_rgb(self) -> Rgb {
+        self.clip_to_gamut().to_srgb8()
+    }
+
+    /// Convert to polar form.
+    #[inline]
+    pub fn to_lch(self) -> OkLch {
+        let c = sqrt(self.a * self.a + self.b * self.b);
+        let h = if c < 1e-6 {
+            0.0
+        } else {
+            atan2(self.b.into(), self.a.into()).to_degrees()
+        };
+        OkLch {
+            l: self.l,
+            c,
+            h: normalize_hue(h as f32),
+        }
+    }
+
+    /// Add `amount` to the lightness (clamped to `0.0..=1.0`), so e.g. `0.1`
+    /// is "10% lighter".
+    #[inline]
+    pub fn lighten(self, amount: f32) -> Self {
+        Self {
+            l: (self.l + amount).clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// The same as `self.lighten(-amount)`.
+    #[inline]
+    pub fn darken(self, amount: f32) -> Self {
+        self.lighten(-amount)
+    }
+
+    /// Scale the chroma by `1.0 + amount`, so `0.2` is 20% more saturated,
+    /// and `-1.0` is grey.
+    #[inline]
+    pub fn saturate(self, amount: f32) -> Self {
+        let k = (1.0 + amount).max(0.0);
+        Self {
+            a: self.a * k,
+            b: self.b * k,
+            ..self
+        }
+    }
+
+    /// Rotate the hue by `degrees`.
+    #[inline]
+    pub fn rotate_hue(self, degrees: f32) -> Self {
+        let mut lch = self.to_lch();
+        lch.h = normalize_hue(lch.h + degrees);
+        lch.to_lab()
+    }
+
+    /// Interpolate between `self` (at `t = 0.0`) and `other` (at `t = 1.0`).
+    #[inline]
+    pub fn mix(self, other: OkLab, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        Self {
+            l: lerp(self.l, other.l, t),
+            a: lerp(self.a, other.a, t),
+            b: lerp(self.b, other.b, t),
+        }
+    }
+
+    /// Whether this is within the sRGB gamut (allowing for a little rounding
+    /// error).
+    #[inline]
+    pub fn in_gamut(self) -> bool {
+        const EPS: f32 = 1e-4;
+        let (r, g, b) = self.to_linear();
+        [r, g, b].iter().all(|&v| (-EPS..=1.0 + EPS).contains(&v))
+    }
+
+    /// Bring this into the sRGB gamut by reducing its chroma, keeping the
+    /// lightness and hue.
+    pub fn clip_to_gamut(self) -> Self {
+        if self.l >= 1.0 {
+            return OkLab {
+                l: 1.0,
+                a: 0.0,
+                b: 0.0,
+            };
+        }
+        if self.l <= 0.0 {
+            return OkLab {
+                l: 0.0,
+                a: 0.0,
+                b: 0.0,
+            };
+        }
+        if self.in_gamut() {
+            return self;
+        }
+        let (mut lo, mut hi) = (0.0f32, 1.0f32);
+        for _ in 0..24 {
+            let mid = (lo + hi) / 2.0;
+            if self.saturate(mid - 1.0).in_gamut() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        self.saturate(lo - 1.0)
+    }
+
+    /// The nearest 256-color palette entry, via [`to_rgb`](Self::to_rgb) and
+    /// [`nearest_ansi256`](crate::nearest_ansi256).
+    #[inline]
+    pub fn nearest_ansi256(self) -> u8 {
+        let (r, g, b) = self.to_rgb();
+        crate::nearest_ansi256(r, g, b)
+    }
+}
+
+impl OkLch {
+    /// Convert from an sRGB color.
+    #[inline]
+    pub fn from_rgb(rgb: Rgb) -> Self {
+        OkLab::from_rgb(rgb).to_lch()
+    }
+
+    /// Convert to an sRGB color, reducing the chroma if it's out of gamut.
+    #[inline]
+    pub fn to_rgb(self) -> Rgb {
+        self.to_lab().to_rgb()
+    }
+
+    /// Convert back to rectangular form.
+    #[inline]
+    pub fn to_lab(self) -> OkLab {
+        let (sin, cos) = sin_cos(f64::from(self.h).to_radians());
+        OkLab {
+            l: self.l,
+            a: self.c * cos as f32,
+            b: self.c * sin as f32,
+        }
+    }
+}
+
+impl From<OkLab> for OkLch {
+    #[inline]
+    fn from(lab: OkLab) -> Self {
+        lab.to_lch()
+    }
+}
+
+impl From<OkLch> for OkLab {
+    #[inline]
+    fn from(lch: OkLch) -> Self {
+        lch.to_lab()
+    }
+}
+
+/// [`OkLab::lighten`], returning the nearest palette index.
+#[inline]
+pub fn lighten_ansi256(rgb: Rgb, amount: f32) -> u8 {
+    OkLab::from_rgb(rgb).lighten(amount).nearest_ansi256()
+}
+
+/// [`OkLab::darken`], returning the nearest palette index.
+#[inline]
+pub fn darken_ansi256(rgb: Rgb, amount: f32) -> u8 {
+    OkLab::from_rgb(rgb).darken(amount).nearest_ansi256()
+}
+
+/// [`OkLab::saturate`], returning the nearest palette index.
+#[inline]
+pub fn saturate_ansi256(rgb: Rgb, amount: f32) -> u8 {
+    OkLab::from_rgb(rgb).saturate(amount).nearest_ansi256()
+}
+
+/// [`OkLab::rotate_hue`], returning the nearest palette index.
+#[inline]
+pub fn rotate_hue_ansi256(rgb: Rgb, degrees: f32) -> u8 {
+    OkLab::from_rgb(rgb).rotate_hue(degrees).nearest_ansi256()
+}
+
+/// [`OkLab::mix`], returning the nearest palette index.
+#[inline]
+pub fn mix_ansi256(a: Rgb, b: Rgb, t: f32) -> u8 {
+    OkLab::from_rgb(a)
+        .mix(OkLab::from_rgb(b), t)
+        .nearest_ansi256()
+}
+
+/// `a` at `t = 0.0`, `b` at `t = 1.0`, and a straight line in between.
+#[inline]
+pub(crate) fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[inline]
+fn normalize_hue(h: f32) -> f32 {
+    let h = h % 360.0;
+    if h < 0.0 {
+        h + 360.0
+    } else {
+        h
+    }
+}
+
+// `core` doesn't have any of the trig functions, so these are simple series
+// evaluated in `f64`, which is far more precise than the `f32` results need.
+
+fn sin_cos(x: f64) -> (f64, f64) {
+    // Reduce to `-π..=π`, where the series converge quickly enough.
+    let turns = x / (2.0 * PI);
+    let x = x - 2.0 * PI * (turns + 0.5f64.copysign(turns)) as i64 as f64;
+    let (mut sin, mut cos) = (0.0, 0.0);
+    let mut term = 1.0;
+    for k in 0..24 {
+        // `term` is `x^k / k!`.
+        match k % 4 {
+            0 => cos += term,
+            1 => sin += term,
+            2 => cos -= term,
+            _ => sin -= term,
+        }
+        term *= x / f64::from(k + 1);
+    }
+    (sin, cos)
+}
+
+fn atan2(y: f64, x: f64) -> f64 {
+    if x == 0.0 {
+        return match y.partial_cmp(&0.0) {
+            Some(core::cmp::Ordering::Less) => -PI / 2.0,
+            Some(core::cmp::Ordering::Greater) => PI / 2.0,
+            _ => 0.0,
+        };
+    }
+    let a = atan(y / x);
+    match (x < 0.0, y < 0.0) {
+        (false, _) => a,
+        (true, false) => a + PI,
+        (true, true) => a - PI,
+    }
+}
+
+fn atan(z: f64) -> f64 {
+    if z.abs() > 1.0 {
+        return z.signum() * PI / 2.0 - atan(1.0 / z);
+    }
+    // Halve the angle, so that `|z| <= tan(π/8)`.
+    let s = f64::from(sqrt((1.0 + z * z) as f32));
+    let s = 0.5 * (s + (1.0 + z * z) / s);
+    let z = z / (1.0 + s);
+    let z2 = z * z;
+    let (mut sum, mut term) = (0.0, z);
+    for k in 0..20 {
+        let t = term / f64::from(2 * k + 1);
+        sum += if k % 2 == 0 { t } else { -t };
+        term *= z2;
+    }
+    2.0 * sum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: f32, b: f32, eps: f32) -> bool {
+        (a - b).abs() <= eps
+    }
+
+    #[test]
+    fn test_math() {
+        for i in -40..=40 {
+            let x = f64::from(i) * 0.37;
+            let (s, c) = sin_cos(x);
+            assert!(
+                (s - x.sin()).abs() < 1e-9 && (c - x.cos()).abs() < 1e-9,
+                "{x}"
+            );
+            for j in -8..=8 {
+                let y = f64::from(j) * 0.61;
+                assert!((atan2(y, x) - y.atan2(x)).abs() < 1e-9, "{y} {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_lch() {
+        for rgb in [
+            (0xff, 0, 0),
+            (0x12, 0x34, 0x56),
+            (0x80, 0x80, 0x80),
+            (0, 0xff, 0x80),
+        ] {
+            let lab = OkLab::from_rgb(rgb);
+            assert_eq!(lab.to_rgb(), rgb);
+            let lch = lab.to_lch();
+            assert!((0.0..360.0).contains(&lch.h));
+            assert_eq!(lch.to_rgb(), rgb);
+            assert_eq!(lab.rotate_hue(360.0).to_rgb(), rgb);
+        }
+        // Red's hue is about 29°, and greys have no chroma.
+        let red = OkLch::from_rgb((0xff, 0, 0));
+        assert!(close(red.h, 29.23, 0.01), "{red:?}");
+        assert!(close(OkLch::from_rgb((0x80, 0x80, 0x80)).c, 0.0, 1e-4));
+    }
+
+    #[test]
+    fn test_adjust() {
+        let c = OkLab::from_rgb((0x3d, 0x7e, 0xd6));
+        assert!(close(c.lighten(0.1).l, c.l + 0.1, 1e-6));
+        assert!(close(c.darken(0.1).l, c.l - 0.1, 1e-6));
+        assert_eq!(c.lighten(2.0).to_rgb(), (0xff, 0xff, 0xff));
+        let grey = c.saturate(-1.0).to_rgb();
+        assert!(grey.0 == grey.1 && grey.1 == grey.2, "{grey:?}");
+        assert_eq!(
+            c.mix(OkLab::from_rgb((0, 0, 0)), 0.0).to_rgb(),
+            (0x3d, 0x7e, 0xd6)
+        );
+        assert_eq!(c.mix(OkLab::from_rgb((0, 0, 0)), 1.0).to_rgb(), (0, 0, 0));
+
+        // Way out of gamut: the lightness and hue survive, and the chroma
+        // doesn't.
+        let vivid = c.saturate(3.0);
+        assert!(!vivid.in_gamut());
+        let clipped = vivid.clip_to_gamut();
+        assert!(clipped.in_gamut());
+        assert!(close(clipped.l, c.l, 1e-6));
+        assert!(close(clipped.to_lch().h, c.to_lch().h, 0.01));
+        assert!(clipped.to_lch().c > c.to_lch().c);
+
+        let rgb = (0x3d, 0x7e, 0xd6);
+        assert_eq!(lighten_ansi256(rgb, 0.1), c.lighten(0.1).nearest_ansi256());
+        assert_eq!(
+            rotate_hue_ansi256(rgb, 0.0),
+            crate::nearest_ansi256(0x3d, 0x7e, 0xd6)
+        );
+        assert_eq!(mix_ansi256(rgb, (0xff, 0xff, 0xff), 1.0), 231);
+        assert_eq!(darken_ansi256(rgb, 1.0), 16);
+    }
+}
@@ -0,0 +1,432 @@
+//! Scientific colormaps (viridis and friends), quantized to the palette.
+//!
+//! The quantization is precomputed (see `test_tables` for how), so a lookup
+//! is just an index into a table. It also takes care to keep the lightness
+//! of the maps that have it increasing: naively taking the nearest entry for
+//! each sample will sometimes step back to a darker entry, which shows up as
+//! a false band in a heat map.
+//!
+//! The colors themselves come from well known polynomial fits (and for
+//! cividis, interpolation through six samples of it), rather than the
+//! original 256-entry tables, so they can be a few units off. This is
+//! invisible after quantization.
+//!
+//! ```
+//! use termpal::colormap::{colormap_ansi256, Colormap};
+//!
+//! let load = [0.1, 0.4, 0.95, 0.7];
+//! for t in load {
+//!     print!("\x1b[48;5;{}m  ", colormap_ansi256(Colormap::Viridis, t));
+//! }
+//! println!("\x1b[0m");
+//! ```
+use crate::gradient::Gradient;
+
+type Rgb = (u8, u8, u8);
+
+/// One of the built-in colormaps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Inferno,
+    /// Like viridis, but designed to look much the same with color vision
+    /// deficiencies.
+    Cividis,
+    /// A rainbow map. Unlike the others, its lightness goes up and then back
+    /// down, so it's better for showing detail than order.
+    Turbo,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 5] = [
+        Colormap::Viridis,
+        Colormap::Magma,
+        Colormap::Inferno,
+        Colormap::Cividis,
+        Colormap::Turbo,
+    ];
+
+    /// The (unquantized) color at `t`, which is clamped to `0.0..=1.0`.
+    pub fn rgb(self, t: f32) -> Rgb {
+        let t = if t > 0.0 { f64::from(t.min(1.0)) } else { 0.0 };
+        let poly = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Turbo => &TURBO,
+            Colormap::Cividis => return Gradient::new(&CIVIDIS).at(t as f32).to_rgb(),
+        };
+        let channel = |c: usize| {
+            let v = poly.iter().rev().fold(0.0, |acc, k| acc * t + k[c]);
+            (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+        };
+        (channel(0), channel(1), channel(2))
+    }
+
+    /// Whether the lightness only ever increases along the map.
+    #[inline]
+    fn monotonic(self) -> bool {
+        self != Colormap::Turbo
+    }
+}
+
+/// The nearest 256-color palette index to `map` at `t` (which is clamped to
+/// `0.0..=1.0`), with the map's lightness order kept intact.
+#[inline]
+pub fn colormap_ansi256(map: Colormap, t: f32) -> u8 {
+    ANSI256[map as usize][sample(t)]
+}
+
+/// The same as [`colormap_ansi256`], for the 88-color palette.
+#[inline]
+#[cfg(feature = "88color")]
+pub fn colormap_ansi88(map: Colormap, t: f32) -> u8 {
+    ANSI88[map as usize][sample(t)]
+}
+
+#[inline]
+fn sample(t: f32) -> usize {
+    let t = if t > 0.0 { t.min(1.0) } else { 0.0 };
+    (t * (SAMPLES - 1) as f32 + 0.5) as usize
+}
+
+const SAMPLES: usize = 256;
+
+// Coefficients of `t^0` to `t^6` for each of r, g and b, from Matt Zucconi's
+// fits to the matplotlib tables.
+#[rustfmt::skip]
+const VIRIDIS: [[f64; 3]; 7] = [
+    [0.2777273272234177, 0.005407344544966578, 0.3340998053353061],
+    [0.1050930431085774, 1.404613529898575, 1.384590162594685],
+    [-0.3308618287255563, 0.214847559468213, 0.09509516302823659],
+    [-4.634230498983486, -5.799100973351585, -19.33244095627987],
+    [6.228269936347081, 14.17993336680509, 56.69055260068105],
+    [4.776384997670288, -13.74514537774601, -65.35303263337234],
+    [-5.435455855934631, 4.645852612178535, 26.3124352495832],
+];
+
+#[rustfmt::skip]
+const MAGMA: [[f64; 3]; 7] = [
+    [-0.002136485053939582, -0.000749655052795221, -0.005386127855323933],
+    [0.2516605407371642, 0.6775232436837668, 2.494026599312351],
+    [8.353717279216625, -3.577719514958484, 0.3144679030132573],
+    [-27.66873308576866, 14.26473078096533, -13.64921318813922],
+    [52.17613981234068, -27.94360607168351, 12.94416944238394],
+    [-50.76852536473588, 29.04658282127291, 4.23415299384598],
+    [18.65570506591883, -11.48977351997711, -5.601961508734096],
+];
+
+#[rustfmt::skip]
+const INFERNO: [[f64; 3]; 7] = [
+    [0.0002189403691192265, 0.001651004631001012, -0.01948089843709184],
+    [0.1065134194856116, 0.5639564367884091, 3.932712388889277],
+    [11.60249308247187, -3.972853965665698, -15.9423941062914],
+    [-41.70399613139459, 17.43639888205313, 44.35414519872813],
+    [77.162935699427, -33.40235894210092, -81.80730925738993],
+    [-71.31942824499214, 32.62606426397723, 73.20951985803202],
+    [25.13112622477341, -12.24266895238567, -23.07032500287172],
+];
+
+// Anton Mikhailov's fit from the turbo announcement, padded to the same shape.
+#[rustfmt::skip]
+const TURBO: [[f64; 3]; 7] = [
+    [0.13572138, 0.09140261, 0.10667330],
+    [4.61539260, 2.19418839, 12.64194608],
+    [-42.66032258, 4.84296658, -60.58204836],
+    [132.13108234, -14.18503333, 110.36276771],
+    [-152.94239396, 4.27729857, -89.90310912],
+    [59.28637943, 2.82956604, 27.34824973],
+    [0.0, 0.0, 0.0],
+];
+
+// Evenly spaced samples of cividis (as in R's viridisLite).
+const CIVIDIS: [Rgb; 6] = [
+    (0x00, 0x20, 0x4d),
+    (0x31, 0x44, 0x6b),
+    (0x66, 0x69, 0x70),
+    (0x95, 0x8f, 0x78),
+    (0xcb, 0xba, 0x69),
+    (0xff, 0xea, 0x46),
+];
+
+/// Generated by `test_tables`, which explains how.
+#[rustfmt::skip]
+static ANSI256: [[u8; SAMPLES]; 5] = [
+    [
+         53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,
+         53,  53,  53,  53,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,
+         54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  24,  24,
+         24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,
+         24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,
+         24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,
+         30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,
+         30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,
+         30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  30,  36,  36,  36,  36,  36,
+         36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  36,
+         36,  36,  36,  36,  36,  36,  36,  36,  36,  36,  71,  71,  71,  71,  71,  71,
+         71,  71,  71,  71,  71,  71,  71,  71,  71,  71,  42,  42,  42,  77,  77,  77,
+         77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77, 113, 113,
+        113, 113, 113, 113, 113, 113, 113, 113, 148, 148, 148, 148, 148, 148, 148, 148,
+        148, 148, 148, 148, 148, 148, 148, 184, 184, 184, 184, 184, 184, 184, 184, 184,
+        184, 184, 184, 184, 184, 184, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220,
+    ],
+    [
+         16,  16, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232,
+        233, 233, 233, 233, 233, 233, 233, 233,  17,  17,  17,  17,  17,  17,  17,  17,
+         17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  53,  53,
+         53,  53,  53,  53,  53,  53,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,
+         54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,  54,
+         54,  54,  54,  54,  90,  90,  90,  90,  90,  90,  90,  90,  90,  90,  90,  90,
+         90,  90,  90,  90,  90,  90,  90,  90,  90,  90,  90, 126, 126, 126, 126, 126,
+        126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126, 126,
+        161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161, 161,
+        161, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168, 168,
+        168, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203, 203,
+        203, 203, 203, 203, 203, 203, 203, 209, 209, 209, 209, 209, 209, 209, 209, 209,
+        209, 209, 209, 209, 209, 209, 209, 209, 209, 209, 209, 209, 216, 216, 216, 216,
+        216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 216, 222,
+        222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222, 222,
+        222, 222, 222, 222, 229, 229, 229, 229, 229, 229, 229, 229, 229, 229, 229, 229,
+    ],
+    [
+         16,  16,  16, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 232, 233,
+        233, 233, 233, 233, 233,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  17,
+         17,  17,  17,  17,  17,  17,  17,  17,  17,  17,  53,  53,  53,  53,  53,  53,
+         53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,
+         53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,  53,
+         89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,  89,
+         89,  89,  89,  89,  89,  89,  89,  89, 125, 125, 125, 125, 125, 125, 125, 125,
+        125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 125, 161,
+        161, 161, 161, 161, 161, 161, 161, 131, 131, 131, 131, 167, 167, 167, 167, 167,
+        167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167, 167,
+        167, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202,
+        202, 202, 202, 202, 202, 208, 208, 208, 208, 208, 208, 208, 208, 208, 208, 208,
+        208, 208, 208, 208, 208, 208, 208, 208, 208, 214, 214, 214, 214, 214, 214, 214,
+        214, 214, 214, 214, 214, 214, 214, 214, 214, 214, 214, 214, 214, 220, 220, 220,
+        220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220, 220,
+        227, 227, 227, 228, 228, 228, 228, 228, 228, 228, 228, 228, 228, 228, 229, 229,
+    ],
+    [
+         17,  17,  17,  17,  17,  17,  17,  17,  17,  17, 235, 235, 235, 235, 236, 236,
+        236, 236, 236, 236, 236, 236, 236, 236, 236, 236, 236, 236, 236, 237, 237, 237,
+        237, 237, 237, 237, 237, 237, 237, 237, 237, 237, 237, 237, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 239, 239, 239, 239, 239, 239,
+        239, 239, 239, 239, 239, 239, 239, 239, 240, 240, 240, 240, 240, 240, 240, 240,
+        240, 240, 240, 240,  59,  59,  59,  59,  59,  59,  59, 241, 241, 241, 241, 241,
+        241, 241, 241, 241, 242, 242, 242, 242, 242, 242, 242, 242, 242, 242, 242, 242,
+        242, 242, 243, 243, 243, 243, 243, 243, 243, 243, 243, 243, 243, 243, 243, 244,
+        244, 244, 244, 244, 244, 244, 244, 244, 244, 244, 102, 102, 102, 102, 102, 102,
+        102, 102, 245, 245, 245, 245, 245, 245, 245, 245, 246, 246, 246, 246, 246, 246,
+        246, 246, 246, 246, 246, 246, 247, 247, 144, 144, 144, 144, 144, 144, 144, 144,
+        144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144, 144,
+        144, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179, 179,
+        179, 179, 179, 179, 179, 179, 179, 185, 185, 185, 185, 185, 185, 185, 185, 185,
+        185, 185, 185, 185, 185, 185, 185, 185, 185, 185, 185, 185, 185, 221, 221, 221,
+        221, 221, 221, 221, 221, 221, 221, 221, 221, 221, 221, 221, 221, 221, 227, 227,
+    ],
+    [
+        234, 234, 235, 235, 236, 236,  53,  53,  54,  54,  54,  54,  55,  55,  55,  55,
+         55,  61,  61,  62,  62,  62,  62,  62,  62,  62,  62,  62,  62,  62,  62,  63,
+         63,  63,  63,  63,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  39,  39,  39,  39,  39,  39,  39,  39,  39,  38,  38,  38,  38,  38,
+         38,  38,  38,  44,  44,  44,  44,  44,  44,  44,  44,  44,  44,  43,  43,  43,
+         43,  43,  43,  43,  43,  43,  79,  78,  78,  49,  49,  49,  49,  49,  49,  49,
+         49,  48,  48,  48,  48,  48,  84,  84,  84,  84,  84,  84,  84,  84,  83,  83,
+         83,  83,  83,  83,  83, 119, 119, 119, 119, 119, 119, 119, 119, 119, 119, 119,
+        119, 119, 119, 119, 155, 155, 155, 155, 155, 155, 155, 155, 155, 155, 155, 184,
+        184, 184, 184, 184, 184, 184, 184, 184, 184, 184, 184, 184, 184, 184, 220, 220,
+        220, 220, 220, 220, 220, 214, 214, 214, 214, 214, 214, 214, 214, 214, 214, 214,
+        214, 214, 214, 214, 214, 208, 208, 208, 208, 208, 208, 208, 208, 208, 208, 208,
+        208, 208, 208, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202, 202,
+        202, 166, 166, 166, 166, 166, 166, 166, 160, 160, 160, 160, 160, 160, 160, 160,
+        160, 160, 160, 124, 124, 124, 124, 124, 124, 124, 124, 124, 124, 124, 124, 124,
+        124, 124, 124, 124,  88,  88,  88,  88,  88,  88,  88,  88,  88,  88,  88,  88,
+    ],
+];
+
+/// Generated by `test_tables`, which explains how.
+#[cfg(feature = "88color")]
+#[rustfmt::skip]
+static ANSI88: [[u8; SAMPLES]; 5] = [
+    [
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  21,  21,  21,  21,
+         21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,
+         21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,
+         21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,
+         21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  21,  25,  25,  25,  25,  25,
+         25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,
+         25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,
+         25,  25,  25,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,
+         40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  40,  56,  56,
+         56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,  56,
+         56,  56,  56,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  76,  76,  76,
+    ],
+    [
+         16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,
+         49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,
+         49,  49,  49,  49,  65,  65,  65,  65,  65,  65,  65,  65,  65,  65,  65,  65,
+         65,  53,  53,  53,  53,  53,  53,  53,  69,  69,  69,  69,  69,  69,  69,  69,
+         69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,
+         69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,  69,
+         69,  69,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,
+         73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,  73,
+         73,  73,  78,  78,  78,  78,  78,  78,  78,  78,  78,  78,  78,  78,  78,  78,
+    ],
+    [
+         16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  16,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,  33,
+         49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,  49,
+         49,  49,  49,  49,  49,  49,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,
+         64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,  64,
+         64,  64,  64,  64,  64,  64,  64,  64,  68,  68,  68,  68,  68,  68,  68,  68,
+         68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,
+         68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,
+         68,  68,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,
+         72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  77,
+         77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,  77,
+    ],
+    [
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,  80,
+         80,  80,  80,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,  81,
+         81,  81,  81,  81,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,
+         82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,  82,
+         82,  82,  82,  82,  82,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,
+         37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,  37,
+         37,  37,  37,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,
+         84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  84,  57,  57,  57,
+         57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,
+         57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,  57,
+         57,  57,  57,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,
+         72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  76,  76,
+    ],
+    [
+         80,  80,  80,  80,  80,  80,  80,  80,  17,  17,  17,  17,  18,  18,  18,  18,
+         18,  18,  18,  34,  34,  34,  34,  19,  19,  23,  23,  23,  23,  23,  23,  23,
+         23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,  23,
+         23,  23,  23,  23,  23,  23,  23,  23,  27,  27,  27,  27,  27,  27,  27,  27,
+         27,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,
+         26,  26,  25,  25,  30,  30,  30,  30,  30,  30,  30,  29,  29,  29,  29,  29,
+         29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+         29,  29,  29,  29,  44,  44,  44,  44,  44,  44,  44,  44,  44,  44,  44,  44,
+         44,  44,  44,  44,  44,  44,  44,  60,  60,  60,  60,  60,  60,  60,  60,  60,
+         60,  60,  56,  56,  56,  56,  56,  56,  56,  56,  56,  72,  72,  72,  72,  72,
+         72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  72,  68,
+         68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,
+         68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  68,  64,  64,  64,  64,  64,
+         64,  64,  64,  64,  64,  64,  64,  64,  48,  48,  48,  48,  48,  48,  48,  48,
+         48,  48,  48,  48,  48,  48,  48,  48,  48,  48,  48,  48,  32,  32,  32,  32,
+         32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,  32,
+    ],
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::imp::fallback::nearest_filtered;
+    use crate::imp::oklab::OkLab;
+    use crate::imp::tab::LAB_PALETTE_ANSI256;
+    use std::string::String;
+
+    /// Quantize each sample of `map` with `nearest`, and then (if the map's
+    /// lightness is monotonic) replace any entry that's darker than the one
+    /// before it with the nearest entry that isn't. `table` holds the Oklab
+    /// values of the entries `nearest` can return, starting at 16.
+    fn quantize(map: Colormap, nearest: fn(u8, u8, u8) -> u8, table: &[OkLab]) -> [u8; SAMPLES] {
+        let lightness = |i: u8| table[i as usize - 16].l;
+        let mut out = [0; SAMPLES];
+        for i in 0..SAMPLES {
+            let (r, g, b) = map.rgb(i as f32 / (SAMPLES - 1) as f32);
+            let mut index = nearest(r, g, b);
+            if map.monotonic() && i > 0 && lightness(index) < lightness(out[i - 1]) {
+                let floor = lightness(out[i - 1]);
+                let lab = OkLab::from_srgb8(r, g, b);
+                let best = nearest_filtered(lab, table, |_, e| e.l >= floor);
+                index = best.map_or(out[i - 1], |j| j as u8 + 16);
+            }
+            out[i] = index;
+        }
+        out
+    }
+
+    fn dump(name: &str, tables: &[[u8; SAMPLES]]) -> String {
+        use core::fmt::Write;
+        let mut s = String::new();
+        let _ = write!(s, "static {name}: [[u8; SAMPLES]; 5] = [");
+        for t in tables {
+            s.push_str("\n    [");
+            for line in t.chunks(16) {
+                s.push_str("\n       ");
+                for i in line {
+                    let _ = write!(s, " {i:3},");
+                }
+            }
+            s.push_str("\n    ],");
+        }
+        s.push_str("\n];");
+        s
+    }
+
+    /// The tables are the output of `quantize`. If you change anything that
+    /// affects them, this prints the new ones to paste in.
+    #[test]
+    fn test_tables() {
+        let ansi256 =
+            Colormap::ALL.map(|m| quantize(m, crate::nearest_ansi256, &LAB_PALETTE_ANSI256));
+        if ansi256 != ANSI256 {
+            std::eprintln!("{}", dump("ANSI256", &ansi256));
+            panic!("ANSI256 is out of date");
+        }
+        #[cfg(feature = "88color")]
+        {
+            use crate::imp::tab::LAB_PALETTE_ANSI88;
+            let ansi88 =
+                Colormap::ALL.map(|m| quantize(m, crate::nearest_ansi88, &LAB_PALETTE_ANSI88));
+            if ansi88 != ANSI88 {
+                std::eprintln!("{}", dump("ANSI88", &ansi88));
+                panic!("ANSI88 is out of date");
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let lightness = |i: u8| LAB_PALETTE_ANSI256[i as usize - 16].l;
+        for map in Colormap::ALL {
+            assert_eq!(colormap_ansi256(map, -1.0), colormap_ansi256(map, 0.0));
+            assert_eq!(colormap_ansi256(map, f32::NAN), colormap_ansi256(map, 0.0));
+            assert_eq!(colormap_ansi256(map, 2.0), colormap_ansi256(map, 1.0));
+            if map.monotonic() {
+                let table = &ANSI256[map as usize];
+                assert!(
+                    table.windows(2).all(|w| lightness(w[0]) <= lightness(w[1])),
+                    "{map:?}"
+                );
+                assert!(lightness(table[0]) < 0.4 && lightness(table[SAMPLES - 1]) > 0.8);
+            }
+        }
+        assert_eq!(Colormap::Viridis.rgb(0.0), (0x47, 0x01, 0x55));
+        assert_eq!(Colormap::Cividis.rgb(1.0), (0xff, 0xea, 0x46));
+    }
+}
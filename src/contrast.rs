@@ -0,0 +1,269 @@
+//! Measuring contrast between text and background colors.
+//!
+//! Two metrics are supported: the [WCAG 2] contrast ratio, which is what most
+//! accessibility audits check, and [APCA] (the candidate for WCAG 3), which
+//! does a much better job with dark backgrounds and knows which color is the
+//! text. See [`nearest_ansi256_with_contrast`](crate::nearest_ansi256_with_contrast)
+//! for picking palette entries that meet a minimum.
+//!
+//! [WCAG 2]: https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+//! [APCA]: https://github.com/Myndex/apca-w3
+use crate::imp::fallback::nearest_filtered;
+use crate::imp::oklab::{srgb8_to_linear, OkLab};
+use crate::imp::tab::LAB_PALETTE_ANSI256;
+
+type Rgb = (u8, u8, u8);
+
+/// A minimum contrast to meet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MinContrast {
+    /// A WCAG 2 contrast ratio, from 1 to 21. AA asks for 4.5 for body text,
+    /// and 3 for large text.
+    Wcag(f32),
+    /// An APCA lightness contrast (Lc), from 0 to about 106. Either polarity
+    /// counts, so this is compared against the absolute value of
+    /// [`apca_lc`]. 75 is the usual minimum for body text, and 60 for larger
+    /// text.
+    Apca(f32),
+}
+
+impl MinContrast {
+    /// How much contrast `text` has against `bg`, in the units of this
+    /// metric.
+    #[inline]
+    fn measure(self, text: Rgb, bg: Rgb) -> f32 {
+        match self {
+            MinContrast::Wcag(_) => wcag_ratio(text, bg),
+            MinContrast::Apca(_) => apca_lc(text, bg).abs(),
+        }
+    }
+
+    #[inline]
+    fn threshold(self) -> f32 {
+        match self {
+            MinContrast::Wcag(v) | MinContrast::Apca(v) => v,
+        }
+    }
+}
+
+/// The WCAG 2 relative luminance of an sRGB color.
+#[inline]
+pub fn relative_luminance((r, g, b): Rgb) -> f32 {
+    0.2126 * srgb8_to_linear(r) + 0.7152 * srgb8_to_linear(g) + 0.0722 * srgb8_to_linear(b)
+}
+
+/// The WCAG 2 contrast ratio between two colors, from 1 (none) to 21 (black
+/// and white). The order doesn't matter.
+///
+/// ```
+/// use termpal::contrast::wcag_ratio;
+///
+/// assert!((wcag_ratio((0, 0, 0), (0xff, 0xff, 0xff)) - 21.0).abs() < 0.001);
+/// assert!((wcag_ratio((0x76, 0x76, 0x76), (0xff, 0xff, 0xff)) - 4.54).abs() < 0.01);
+/// ```
+pub fn wcag_ratio(a: Rgb, b: Rgb) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    let (hi, lo) = if la > lb { (la, lb) } else { (lb, la) };
+    (hi + 0.05) / (lo + 0.05)
+}
+
+/// The APCA (0.0.98G-4g) lightness contrast of `text` on `bg`.
+///
+/// This is positive for dark text on a light background, and negative for
+/// light text on a dark one. Values under about 7.5 in magnitude are clamped
+/// to 0, as they're not meaningful.
+///
+/// ```
+/// use termpal::contrast::apca_lc;
+///
+/// let lc = apca_lc((0x88, 0x88, 0x88), (0xff, 0xff, 0xff));
+/// assert!((lc - 63.06).abs() < 0.1, "{lc}");
+/// let lc = apca_lc((0xff, 0xff, 0xff), (0x88, 0x88, 0x88));
+/// assert!((lc + 68.54).abs() < 0.1, "{lc}");
+/// ```
+pub fn apca_lc(text: Rgb, bg: Rgb) -> f32 {
+    const OFFSET: f64 = 0.027;
+    const CLIP: f64 = 0.1;
+
+    let sapc = sapc(text, bg);
+    let lc = if sapc >= 0.0 {
+        if sapc < CLIP {
+            0.0
+        } else {
+            sapc - OFFSET
+        }
+    } else if sapc > -CLIP {
+        0.0
+    } else {
+        sapc + OFFSET
+    };
+    (lc * 100.0) as f32
+}
+
+/// The APCA contrast before the low end is clipped and offset (and so on a
+/// 0 to about 1.1 scale, rather than Lc's 0 to about 106), which unlike
+/// [`apca_lc`] keeps going all the way down to 0.
+pub(crate) fn sapc(text: Rgb, bg: Rgb) -> f64 {
+    // These are all from the reference implementation.
+    const BLACK_THRESHOLD: f64 = 0.022;
+    const BLACK_CLAMP: f64 = 1.414;
+    const DELTA_Y_MIN: f64 = 0.0005;
+    const SCALE: f64 = 1.14;
+
+    let y = |(r, g, b): Rgb| {
+        // APCA uses a plain 2.4 exponent, rather than the piecewise sRGB curve
+        // (and so `SRGB_TAB`).
+        let c = |v: u8| powf(f64::from(v) / 255.0, 2.4);
+        let y = 0.2126729 * c(r) + 0.7151522 * c(g) + 0.0721750 * c(b);
+        if y < BLACK_THRESHOLD {
+            y + powf(BLACK_THRESHOLD - y, BLACK_CLAMP)
+        } else {
+            y
+        }
+    };
+    let (yt, yb) = (y(text), y(bg));
+    if (yb - yt).abs() < DELTA_Y_MIN {
+        0.0
+    } else if yb > yt {
+        (powf(yb, 0.56) - powf(yt, 0.57)) * SCALE
+    } else {
+        (powf(yb, 0.65) - powf(yt, 0.62)) * SCALE
+    }
+}
+
+/// `x.powf(y)` for `x` in `0.0..=1.0`, as `core` doesn't have it.
+fn powf(x: f64, y: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    exp2(y * log2(x))
+}
+
+fn log2(x: f64) -> f64 {
+    // Split into `m * 2^e`, with `m` in `[sqrt(1/2), sqrt(2))`, so that the
+    // series below converges quickly.
+    let bits = x.to_bits();
+    let mut e = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut m = f64::from_bits((bits & !(0x7ff << 52)) | (1023 << 52));
+    if m > core::f64::consts::SQRT_2 {
+        m /= 2.0;
+        e += 1;
+    }
+    // ln(m) = 2 atanh(s), where s = (m - 1) / (m + 1).
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let mut term = s;
+    let mut sum = 0.0;
+    for k in 0..10 {
+        sum += term / f64::from(2 * k + 1);
+        term *= s2;
+    }
+    e as f64 + 2.0 * sum * core::f64::consts::LOG2_E
+}
+
+fn exp2(x: f64) -> f64 {
+    if x < -1022.0 {
+        return 0.0;
+    }
+    let n = x as i64 - i64::from(x < 0.0 && x != (x as i64) as f64);
+    let f = (x - n as f64) * core::f64::consts::LN_2;
+    // e^f for f in [0, ln 2).
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..16 {
+        term *= f / f64::from(k);
+        sum += term;
+    }
+    sum * f64::from_bits(((n + 1023) as u64) << 52)
+}
+
+pub(crate) fn nearest_ansi256_with_contrast(fg: Rgb, bg: Rgb, min: MinContrast) -> u8 {
+    let nearest = crate::nearest_ansi256(fg.0, fg.1, fg.2);
+    if min.measure(crate::ansi256_to_rgb(nearest), bg) >= min.threshold() {
+        return nearest;
+    }
+    let lab = OkLab::from_srgb8(fg.0, fg.1, fg.2);
+    let contrast = |i: usize| min.measure(crate::ansi256_to_rgb(i as u8 + 16), bg);
+    // The nearest entry that's good enough, or failing that, the one with the
+    // most contrast (which will be black or white, or close to it).
+    let best = nearest_filtered(lab, &LAB_PALETTE_ANSI256, |i, _| {
+        contrast(i) >= min.threshold()
+    })
+    .unwrap_or_else(|| {
+        (0..LAB_PALETTE_ANSI256.len())
+            .max_by(|&x, &y| contrast(x).total_cmp(&contrast(y)))
+            .unwrap_or(0)
+    });
+    best as u8 + 16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ansi256_to_rgb, nearest_ansi256_with_contrast};
+
+    #[test]
+    fn test_metrics() {
+        assert_eq!(wcag_ratio((0xff, 0xff, 0xff), (0xff, 0xff, 0xff)), 1.0);
+        let r = wcag_ratio((0xff, 0, 0), (0xff, 0xff, 0xff));
+        assert!((r - 4.0).abs() < 0.01, "{r}");
+
+        // Values from the reference implementation (`#888` and `#fff` are the
+        // ones in its README).
+        for (text, bg, expected) in [
+            ((0x88, 0x88, 0x88), (0xff, 0xff, 0xff), 63.06),
+            ((0, 0, 0), (0xff, 0xff, 0xff), 106.04),
+            ((0xff, 0xff, 0xff), (0, 0, 0), -107.88),
+            ((0x12, 0x34, 0x56), (0xe9, 0xe4, 0xd0), 82.46),
+            ((0xaa, 0xaa, 0xaa), (0x22, 0x22, 0x22), -53.82),
+        ] {
+            let lc = apca_lc(text, bg);
+            assert!((lc - expected).abs() < 0.1, "{text:?} {bg:?} {lc}");
+        }
+        assert_eq!(apca_lc((0x80, 0x80, 0x80), (0x81, 0x81, 0x81)), 0.0);
+
+        for x in [0.001, 0.02, 0.5, 0.999, 1.0] {
+            for y in [0.56, 1.414, 2.4] {
+                let want = std::primitive::f64::powf(x, y);
+                assert!((powf(x, y) - want).abs() < 1e-12, "{x} {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_contrast() {
+        // Already fine, so it's the same as `nearest_ansi256`.
+        let black_bg = 16u8;
+        assert_eq!(
+            nearest_ansi256_with_contrast((0xff, 0xff, 0x00), black_bg, MinContrast::Wcag(4.5)),
+            226
+        );
+
+        // A dim blue on black needs to get lighter.
+        let fg = (0x20, 0x30, 0x90);
+        for min in [
+            MinContrast::Wcag(4.5),
+            MinContrast::Wcag(7.0),
+            MinContrast::Apca(60.0),
+        ] {
+            let i = nearest_ansi256_with_contrast(fg, black_bg, min);
+            assert!(i >= 16);
+            assert!(
+                min.measure(ansi256_to_rgb(i), (0, 0, 0)) >= min.threshold(),
+                "{min:?} {i}"
+            );
+            // And still be blue-ish.
+            let (r, _, b) = ansi256_to_rgb(i);
+            assert!(b > r, "{min:?} {i}");
+            // An RGB background is the same as the index for it.
+            assert_eq!(nearest_ansi256_with_contrast(fg, (0, 0, 0), min), i);
+        }
+
+        // Impossible, so we get as close as we can.
+        let i = nearest_ansi256_with_contrast(fg, 244, MinContrast::Wcag(21.0));
+        let best = (16..=255)
+            .map(|j| wcag_ratio(ansi256_to_rgb(j), ansi256_to_rgb(244)))
+            .fold(0.0, f32::max);
+        assert_eq!(wcag_ratio(ansi256_to_rgb(i), ansi256_to_rgb(244)), best);
+    }
+}
@@ -0,0 +1,470 @@
+//! Simulating color vision deficiencies, and checking that theme colors stay
+//! distinguishable once quantized.
+//!
+//! The simulations cover the three kinds of dichromacy (protanopia,
+//! deuteranopia and tritanopia), using the usual matrices from [Brettel et
+//! al. 1997], [Viénot et al. 1999] or [Machado et al. 2009], all applied in
+//! linear RGB. The coefficients are the ones used by [DaltonLens] (for the
+//! first two) and the paper (for Machado, at full severity).
+//!
+//! Red and green status colors are the classic trap: they can look fine
+//! side by side in 24-bit color, only to land on palette entries that a
+//! deuteranope can't tell apart. A [`Checker`] finds those pairs.
+//!
+//! ```
+//! # #[cfg(feature = "alloc")] {
+//! use termpal::cvd::Checker;
+//!
+//! let (ok, error) = ((0x5f, 0x87, 0x00), (0xaf, 0x5f, 0x00));
+//! for c in Checker::new().check_ansi256(&[ok, error]) {
+//!     println!("{:?} and {:?} collide ({:?})", c.indices.0, c.indices.1, c.vision);
+//! }
+//! # }
+//! ```
+//!
+//! [Brettel et al. 1997]: https://doi.org/10.1364/JOSAA.14.002647
+//! [Viénot et al. 1999]: https://doi.org/10.1002/(SICI)1520-6378(199908)24:4%3C243::AID-COL5%3E3.0.CO;2-3
+//! [Machado et al. 2009]: https://doi.org/10.1109/TVCG.2009.113
+//! [DaltonLens]: https://github.com/DaltonLens/libDaltonLens
+use crate::imp::oklab::{linear_to_srgb8, sqrt, srgb8_to_linear, OkLab};
+
+type Rgb = (u8, u8, u8);
+type Matrix = [[f32; 3]; 3];
+
+/// A kind of color vision deficiency, simulated as the complete absence of
+/// one type of cone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Deficiency {
+    /// No long-wavelength ("red") cones.
+    Protanopia,
+    /// No medium-wavelength ("green") cones. This is the most common.
+    Deuteranopia,
+    /// No short-wavelength ("blue") cones. This is rare.
+    Tritanopia,
+}
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 3] = [
+        Deficiency::Protanopia,
+        Deficiency::Deuteranopia,
+        Deficiency::Tritanopia,
+    ];
+}
+
+/// Which simulation to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Method {
+    /// Brettel, Viénot and Mollon (1997), which projects onto one of two
+    /// half-planes. This is the most accurate for all three, and is the
+    /// default.
+    #[default]
+    Brettel,
+    /// Viénot, Brettel and Mollon (1999): a single matrix, which is just as
+    /// good as Brettel for protanopia and deuteranopia, but not for
+    /// tritanopia.
+    Vienot,
+    /// Machado, Oliveira and Fernandes (2009), at a severity of 1.0.
+    Machado,
+}
+
+/// Brettel's two matrices, and the normal of the plane that separates them.
+struct Brettel {
+    first: Matrix,
+    second: Matrix,
+    normal: [f32; 3],
+}
+
+const BRETTEL_PROTAN: Brettel = Brettel {
+    first: [
+        [0.14510, 1.20165, -0.34675],
+        [0.10447, 0.85316, 0.04237],
+        [0.00429, -0.00603, 1.00174],
+    ],
+    second: [
+        [0.14115, 1.16782, -0.30897],
+        [0.10495, 0.85730, 0.03776],
+        [0.00431, -0.00586, 1.00155],
+    ],
+    normal: [0.00048, 0.00416, -0.00464],
+};
+
+const BRETTEL_DEUTAN: Brettel = Brettel {
+    first: [
+        [0.36198, 0.86755, -0.22953],
+        [0.26099, 0.64512, 0.09389],
+        [-0.01975, 0.02686, 0.99289],
+    ],
+    second: [
+        [0.37009, 0.88540, -0.25549],
+        [0.25767, 0.63782, 0.10451],
+        [-0.01950, 0.02741, 0.99209],
+    ],
+    normal: [-0.00293, -0.00645, 0.00938],
+};
+
+const BRETTEL_TRITAN: Brettel = Brettel {
+    first: [
+        [1.01354, 0.14268, -0.15622],
+        [-0.01181, 0.87561, 0.13619],
+        [0.07707, 0.81208, 0.11085],
+    ],
+    second: [
+        [0.93337, 0.19999, -0.13336],
+        [0.05809, 0.82565, 0.11626],
+        [-0.37923, 1.13825, 0.24098],
+    ],
+    normal: [0.03960, -0.02831, -0.01129],
+};
+
+const VIENOT_PROTAN: Matrix = [
+    [0.11238, 0.88762, 0.00000],
+    [0.11238, 0.88762, 0.00000],
+    [0.00401, -0.00401, 1.00000],
+];
+
+const VIENOT_DEUTAN: Matrix = [
+    [0.29275, 0.70725, 0.00000],
+    [0.29275, 0.70725, 0.00000],
+    [-0.02234, 0.02234, 1.00000],
+];
+
+const VIENOT_TRITAN: Matrix = [
+    [1.00000, 0.14461, -0.14461],
+    [0.00000, 0.85924, 0.14076],
+    [0.00000, 0.85924, 0.14076],
+];
+
+const MACHADO_PROTAN: Matrix = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+
+const MACHADO_DEUTAN: Matrix = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+
+const MACHADO_TRITAN: Matrix = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+#[inline]
+fn mul(m: &Matrix, [r, g, b]: [f32; 3]) -> [f32; 3] {
+    let row = |i: usize| m[i][0] * r + m[i][1] * g + m[i][2] * b;
+    [row(0), row(1), row(2)]
+}
+
+/// Simulate how `rgb` looks with `deficiency`, as a linear RGB triple (which
+/// can be a little outside of `0.0..=1.0`).
+pub(crate) fn simulate_linear(rgb: [f32; 3], deficiency: Deficiency, method: Method) -> [f32; 3] {
+    match method {
+        Method::Brettel => {
+            let b = match deficiency {
+                Deficiency::Protanopia => &BRETTEL_PROTAN,
+                Deficiency::Deuteranopia => &BRETTEL_DEUTAN,
+                Deficiency::Tritanopia => &BRETTEL_TRITAN,
+            };
+            let n = &b.normal;
+            let side = rgb[0] * n[0] + rgb[1] * n[1] + rgb[2] * n[2];
+            mul(if side >= 0.0 { &b.first } else { &b.second }, rgb)
+        }
+        Method::Vienot => match deficiency {
+            Deficiency::Protanopia => mul(&VIENOT_PROTAN, rgb),
+            Deficiency::Deuteranopia => mul(&VIENOT_DEUTAN, rgb),
+            Deficiency::Tritanopia => mul(&VIENOT_TRITAN, rgb),
+        },
+        Method::Machado => match deficiency {
+            Deficiency::Protanopia => mul(&MACHADO_PROTAN, rgb),
+            Deficiency::Deuteranopia => mul(&MACHADO_DEUTAN, rgb),
+            Deficiency::Tritanopia => mul(&MACHADO_TRITAN, rgb),
+        },
+    }
+}
+
+/// Simulate how `(r, g, b)` looks with `deficiency`.
+///
+/// ```
+/// use termpal::cvd::{simulate, Deficiency, Method};
+///
+/// // Pure red and green both look a murky yellow without green cones.
+/// let red = simulate((0xff, 0, 0), Deficiency::Deuteranopia, Method::Brettel);
+/// let green = simulate((0, 0xff, 0), Deficiency::Deuteranopia, Method::Brettel);
+/// assert!(red.0 > red.2 && green.0 > green.2);
+/// ```
+pub fn simulate((r, g, b): Rgb, deficiency: Deficiency, method: Method) -> Rgb {
+    let lin = [r, g, b].map(srgb8_to_linear);
+    let [r, g, b] = simulate_linear(lin, deficiency, method).map(linear_to_srgb8);
+    (r, g, b)
+}
+
+/// The default [`Checker::min_distance`], in Oklab units.
+///
+/// This is a few times the just-noticeable difference, which is about what it
+/// takes to tell two small swatches of text apart at a glance.
+pub const DEFAULT_MIN_DISTANCE: f32 = 0.06;
+
+/// Two theme colors that can't be told apart once quantized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    /// The positions of the two colors in the theme, with `first < second`.
+    pub first: usize,
+    pub second: usize,
+    /// The palette entries they were quantized to.
+    pub indices: (u8, u8),
+    /// Who can't tell them apart, with `None` meaning normal vision (as
+    /// happens when both colors land on the same entry).
+    pub vision: Option<Deficiency>,
+    /// The Oklab distance between the entries, as seen with `vision`.
+    pub distance: f32,
+}
+
+/// Checks that a set of theme colors stay distinguishable, with normal vision
+/// and with each [`Deficiency`], after quantization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checker {
+    method: Method,
+    min_distance: f32,
+}
+
+impl Checker {
+    /// A checker using [`Method::Brettel`] and [`DEFAULT_MIN_DISTANCE`].
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            method: Method::Brettel,
+            min_distance: DEFAULT_MIN_DISTANCE,
+        }
+    }
+
+    /// Which simulation to use.
+    #[inline]
+    pub const fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Set the smallest Oklab distance two entries can be apart (as seen by
+    /// everyone) without counting as a collision.
+    #[inline]
+    pub const fn min_distance(mut self, min_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self
+    }
+
+    /// The smallest distance between the palette entries `a` and `b` with
+    /// normal vision or any deficiency, along with who sees that.
+    fn closest(&self, a: u8, b: u8) -> (Option<Deficiency>, f32) {
+        let lin = |i: u8| {
+            let (r, g, b) = crate::ansi256_to_rgb(i);
+            [r, g, b].map(srgb8_to_linear)
+        };
+        let (la, lb) = (lin(a), lin(b));
+        let dist = |[xr, xg, xb]: [f32; 3], [yr, yg, yb]: [f32; 3]| {
+            sqrt(OkLab::from_linear(xr, xg, xb).dist_sq(&OkLab::from_linear(yr, yg, yb)))
+        };
+        let mut worst = (None, dist(la, lb));
+        for d in Deficiency::ALL {
+            let sim = |v| simulate_linear(v, d, self.method);
+            let dd = dist(sim(la), sim(lb));
+            if dd < worst.1 {
+                worst = (Some(d), dd);
+            }
+        }
+        worst
+    }
+
+    /// The collisions between `colors` (a theme, as 24-bit colors) when each
+    /// is quantized to its [`nearest_ansi256`](crate::nearest_ansi256) entry.
+    ///
+    /// Colors that are exactly the same in the theme are assumed to be meant
+    /// that way, and aren't reported. Each pair is reported at most once,
+    /// for whoever has the hardest time with it.
+    #[cfg(feature = "alloc")]
+    pub fn check_ansi256(&self, colors: &[Rgb]) -> alloc::vec::Vec<Collision> {
+        let indices: alloc::vec::Vec<u8> = colors
+            .iter()
+            .map(|&(r, g, b)| crate::nearest_ansi256(r, g, b))
+            .collect();
+        self.collisions(colors, &indices)
+    }
+
+    /// The same as [`check_ansi256`](Self::check_ansi256), for `colors` that
+    /// have already been quantized to `indices`.
+    ///
+    /// # Panics
+    ///
+    /// If `colors` and `indices` are different lengths.
+    #[cfg(feature = "alloc")]
+    #[track_caller]
+    pub fn collisions(&self, colors: &[Rgb], indices: &[u8]) -> alloc::vec::Vec<Collision> {
+        assert_eq!(colors.len(), indices.len(), "one index per color");
+        let mut out = alloc::vec::Vec::new();
+        for second in 0..colors.len() {
+            for first in 0..second {
+                if colors[first] == colors[second] {
+                    continue;
+                }
+                let (a, b) = (indices[first], indices[second]);
+                let (vision, distance) = self.closest(a, b);
+                if distance < self.min_distance {
+                    out.push(Collision {
+                        first,
+                        second,
+                        indices: (a, b),
+                        vision,
+                        distance,
+                    });
+                }
+            }
+        }
+        out.sort_by_key(|c| (c.first, c.second));
+        out
+    }
+
+    /// Quantize `colors`, moving colors off their nearest entry where that
+    /// avoids a collision.
+    ///
+    /// Colors are placed in order, each on the closest entry (to the
+    /// original color) that doesn't collide with any placed so far, so put
+    /// the ones that matter most first. Colors that can't be placed without a
+    /// collision get their nearest entry. Returns the indices, along with any
+    /// collisions that are left.
+    #[cfg(feature = "alloc")]
+    pub fn quantize_ansi256(
+        &self,
+        colors: &[Rgb],
+    ) -> (alloc::vec::Vec<u8>, alloc::vec::Vec<Collision>) {
+        use crate::imp::fallback::nearest_filtered;
+        use crate::imp::tab::LAB_PALETTE_ANSI256;
+
+        let mut indices: alloc::vec::Vec<u8> = alloc::vec::Vec::with_capacity(colors.len());
+        for (i, &(r, g, b)) in colors.iter().enumerate() {
+            if let Some(j) = colors[..i].iter().position(|&c| c == (r, g, b)) {
+                indices.push(indices[j]);
+                continue;
+            }
+            let nearest = crate::nearest_ansi256(r, g, b);
+            let placed = |index: u8| {
+                (0..i).all(|j| {
+                    colors[j] == colors[i] || self.closest(indices[j], index).1 >= self.min_distance
+                })
+            };
+            if placed(nearest) {
+                indices.push(nearest);
+                continue;
+            }
+            let lab = OkLab::from_srgb8(r, g, b);
+            let index = nearest_filtered(lab, &LAB_PALETTE_ANSI256, |k, _| placed(k as u8 + 16))
+                .map_or(nearest, |k| k as u8 + 16);
+            indices.push(index);
+        }
+        let collisions = self.collisions(colors, &indices);
+        (indices, collisions)
+    }
+}
+
+impl Default for Checker {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simulate() {
+        for method in [Method::Brettel, Method::Vienot, Method::Machado] {
+            for d in Deficiency::ALL {
+                // Greys are left (almost) alone.
+                for v in [0, 0x40, 0x80, 0xff] {
+                    let (r, g, b) = simulate((v, v, v), d, method);
+                    for c in [r, g, b] {
+                        assert!(c.abs_diff(v) <= 1, "{method:?} {d:?} {v} {c}");
+                    }
+                }
+            }
+            // Red and green get much closer for protans and deutans, but not
+            // for tritans.
+            let dist = |d| {
+                let (a, b) = (
+                    simulate((0xd7, 0x00, 0x00), d, method),
+                    simulate((0x00, 0xaf, 0x00), d, method),
+                );
+                let (a, b) = (
+                    OkLab::from_srgb8(a.0, a.1, a.2),
+                    OkLab::from_srgb8(b.0, b.1, b.2),
+                );
+                let (da, db) = (a.a - b.a, a.b - b.b);
+                sqrt(da * da + db * db)
+            };
+            let tritan = dist(Deficiency::Tritanopia);
+            assert!(dist(Deficiency::Protanopia) < tritan / 2.0, "{method:?}");
+            assert!(dist(Deficiency::Deuteranopia) < tritan / 2.0, "{method:?}");
+        }
+        // Blue and green for tritans.
+        let b = simulate((0x00, 0x5f, 0xff), Deficiency::Tritanopia, Method::Brettel);
+        let g = simulate((0x00, 0xaf, 0x87), Deficiency::Tritanopia, Method::Brettel);
+        assert!(b.1 > b.2 / 2 && g.1 > g.2 / 2, "{b:?} {g:?}");
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_check() {
+        let checker = Checker::new();
+        // A green and an orange that are distinct in 24-bit color, but
+        // only by hue along the red/green axis.
+        let theme = [(0x5f, 0x87, 0x00), (0xaf, 0x5f, 0x00), (0x00, 0x00, 0xd7)];
+        let found = checker.check_ansi256(&theme);
+        assert_eq!(found.len(), 1, "{found:?}");
+        let c = found[0];
+        assert_eq!((c.first, c.second), (0, 1));
+        assert_eq!(c.indices, (64, 130));
+        assert!(matches!(
+            c.vision,
+            Some(Deficiency::Protanopia | Deficiency::Deuteranopia)
+        ));
+        assert!(c.distance < DEFAULT_MIN_DISTANCE);
+
+        // Two colors on the same entry collide for everyone.
+        let theme = [(0xff, 0x5f, 0x5f), (0xf0, 0x60, 0x60), (0xff, 0x5f, 0x5f)];
+        let found = checker.check_ansi256(&theme);
+        assert_eq!(found.len(), 2, "{found:?}");
+        assert!(found
+            .iter()
+            .all(|c| c.vision.is_none() && c.distance == 0.0));
+
+        let (indices, left) = checker.quantize_ansi256(&theme);
+        assert!(left.is_empty(), "{left:?}");
+        assert_eq!((indices[0], indices[2]), (203, 203));
+        assert_ne!(indices[1], 203);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_quantize() {
+        let checker = Checker::new();
+        let theme = [(0x5f, 0x87, 0x00), (0xaf, 0x5f, 0x00)];
+        let (indices, left) = checker.quantize_ansi256(&theme);
+        assert!(left.is_empty(), "{left:?}");
+        // The first stays put, and the second moves somewhere nearby.
+        assert_eq!(indices[0], 64);
+        assert_ne!(indices[1], 130);
+        assert!(checker.collisions(&theme, &indices).is_empty());
+        let moved = OkLab::from_srgb8(0xaf, 0x5f, 0x00);
+        let (r, g, b) = crate::ansi256_to_rgb(indices[1]);
+        let e = OkLab::from_srgb8(r, g, b);
+        assert!(sqrt(e.dist_sq(&moved)) < 0.15, "{}", indices[1]);
+
+        // With an impossible minimum, everything gets its nearest entry.
+        let (indices, left) = checker.min_distance(2.0).quantize_ansi256(&theme);
+        assert_eq!(indices, [64, 130]);
+        assert_eq!(left.len(), 1);
+    }
+}
@@ -0,0 +1,276 @@
+//! Working out which [`ColorDepth`] to use, from the environment.
+//!
+//! There's no reliable way to do this, so this follows the conventions most
+//! tools have settled on, in order:
+//!
+//! 1. [`NO_COLOR`](https://no-color.org), if set to anything non-empty,
+//!    disables color.
+//! 2. `FORCE_COLOR` forces color on, even if the output isn't a terminal. As
+//!    in Node's `chalk`, `0` or `false` disable it instead, and `1` (or
+//!    `true`), `2` and `3` select 16, 256 and true color. Any other value, and
+//!    `CLICOLOR_FORCE` (if not `0`), force color on at whatever depth is
+//!    detected.
+//! 3. Otherwise, there's no color if the output isn't a terminal, if
+//!    `CLICOLOR` is `0`, or if `TERM` is `dumb`.
+//! 4. `COLORTERM=truecolor` (or `24bit`) means true color.
+//! 5. If there's a terminfo entry for `TERM`, its `RGB` or `Tc` capabilities
+//!    mean true color, and otherwise its `colors` is used.
+//! 6. Failing that, `TERM` is checked for things like `256color`. Any other
+//!    terminal is assumed to support the 16 named colors.
+//!
+//! Note that this doesn't (and can't) tell you what the colors *are*. For
+//! that, see the [`query`](crate::query) module.
+use crate::terminfo;
+use crate::ColorDepth;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::vec::Vec;
+
+/// The depth to use for standard output, or `None` if color shouldn't be
+/// used.
+///
+/// ```
+/// use termpal::sgr::{Color, Style};
+///
+/// if let Some(depth) = termpal::detect::stdout() {
+///     let style = Style::new().fg(Color::Rgb(0xff, 0x80, 0x00));
+///     println!("{}orange\x1b[0m", style.display(depth));
+/// } else {
+///     println!("orange");
+/// }
+/// ```
+#[inline]
+pub fn stdout() -> Option<ColorDepth> {
+    color_depth(io::stdout().is_terminal())
+}
+
+/// The same as [`stdout`], but for standard error.
+#[inline]
+pub fn stderr() -> Option<ColorDepth> {
+    color_depth(io::stderr().is_terminal())
+}
+
+/// The depth to use for an output stream, given whether or not it's a
+/// terminal, or `None` if color shouldn't be used.
+pub fn color_depth(is_terminal: bool) -> Option<ColorDepth> {
+    let var = |name: &str| env::var_os(name).and_then(|v| v.into_string().ok());
+    detect(is_terminal, var, |term| {
+        let data = read_terminfo(term)?;
+        let info = terminfo::Terminfo::parse(&data).ok()?;
+        Some(Caps {
+            colors: info.colors(),
+            truecolor: info.truecolor(),
+        })
+    })
+}
+
+/// What to use for an 88-color terminal, which is only supported with the
+/// `88color` feature.
+#[cfg(feature = "88color")]
+const ANSI88: ColorDepth = ColorDepth::Ansi88;
+#[cfg(not(feature = "88color"))]
+const ANSI88: ColorDepth = ColorDepth::Ansi16;
+
+/// The capabilities we care about from a terminfo entry.
+struct Caps {
+    colors: Option<i32>,
+    truecolor: bool,
+}
+
+fn detect(
+    is_terminal: bool,
+    var: impl Fn(&str) -> Option<String>,
+    terminfo: impl FnOnce(&str) -> Option<Caps>,
+) -> Option<ColorDepth> {
+    if var("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return None;
+    }
+    // `Some(None)` means forced on, but at whatever depth we detect.
+    let forced = match var("FORCE_COLOR").as_deref() {
+        Some("0" | "false") => return None,
+        Some("1" | "true") => Some(Some(ColorDepth::Ansi16)),
+        Some("2") => Some(Some(ColorDepth::Ansi256)),
+        Some("3") => Some(Some(ColorDepth::TrueColor)),
+        Some(_) => Some(None),
+        None => var("CLICOLOR_FORCE").filter(|v| v != "0").map(|_| None),
+    };
+    match forced {
+        Some(Some(depth)) => return Some(depth),
+        Some(None) => {}
+        None if !is_terminal || var("CLICOLOR").as_deref() == Some("0") => return None,
+        None => {}
+    }
+    let detected = detect_depth(&var, terminfo);
+    if forced.is_some() {
+        detected.or(Some(ColorDepth::Ansi16))
+    } else {
+        detected
+    }
+}
+
+fn detect_depth(
+    var: &impl Fn(&str) -> Option<String>,
+    terminfo: impl FnOnce(&str) -> Option<Caps>,
+) -> Option<ColorDepth> {
+    let term = var("TERM").unwrap_or_default();
+    if term == "dumb" {
+        return None;
+    }
+    if matches!(var("COLORTERM").as_deref(), Some("truecolor" | "24bit")) {
+        return Some(ColorDepth::TrueColor);
+    }
+    if let Some(caps) = Some(term.as_str())
+        .filter(|t| !t.is_empty())
+        .and_then(terminfo)
+    {
+        return match caps.colors {
+            _ if caps.truecolor => Some(ColorDepth::TrueColor),
+            Some(n) if n >= 1 << 24 => Some(ColorDepth::TrueColor),
+            Some(n) if n >= 256 => Some(ColorDepth::Ansi256),
+            Some(n) if n >= 88 => Some(ANSI88),
+            Some(n) if n >= 8 => Some(ColorDepth::Ansi16),
+            _ => None,
+        };
+    }
+    if term.ends_with("-direct") || term.ends_with("truecolor") {
+        Some(ColorDepth::TrueColor)
+    } else if term.contains("256color") {
+        Some(ColorDepth::Ansi256)
+    } else if term.contains("88color") {
+        Some(ANSI88)
+    } else if !term.is_empty() {
+        Some(ColorDepth::Ansi16)
+    } else if var("WT_SESSION").is_some() {
+        // Windows Terminal, which doesn't set `TERM`.
+        Some(ColorDepth::TrueColor)
+    } else {
+        None
+    }
+}
+
+/// Find and read the compiled terminfo entry for `term`, searching the same
+/// directories as ncurses.
+fn read_terminfo(term: &str) -> Option<Vec<u8>> {
+    if term.is_empty() || term.contains(['/', '\\']) || term.starts_with('.') {
+        return None;
+    }
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    dirs.extend(env::var_os("TERMINFO").map(PathBuf::from));
+    dirs.extend(env::var_os("HOME").map(|h| Path::new(&h).join(".terminfo")));
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        for dir in env::split_paths(&list) {
+            // An empty entry means the default location.
+            if dir.as_os_str().is_empty() {
+                dirs.push("/usr/share/terminfo".into());
+            } else {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs.extend(
+        [
+            "/etc/terminfo",
+            "/lib/terminfo",
+            "/usr/share/terminfo",
+            "/usr/lib/terminfo",
+            "/usr/share/lib/terminfo",
+            "/usr/local/share/terminfo",
+            "/opt/homebrew/share/terminfo",
+        ]
+        .map(PathBuf::from),
+    );
+    let letter = term.chars().next()?;
+    let byte = term.as_bytes()[0];
+    dirs.iter().find_map(|dir| {
+        // Usually the subdirectory is the first letter, but on macOS it's
+        // that letter's hex code.
+        let letter = dir.join(letter.encode_utf8(&mut [0; 4])).join(term);
+        let hex = dir.join(std::format!("{byte:02x}")).join(term);
+        std::fs::read(letter).or_else(|_| std::fs::read(hex)).ok()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(
+        is_terminal: bool,
+        vars: &[(&str, &str)],
+        caps: Option<(i32, bool)>,
+    ) -> Option<ColorDepth> {
+        detect(
+            is_terminal,
+            |name| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| String::from(*v))
+            },
+            |_| {
+                caps.map(|(colors, truecolor)| Caps {
+                    colors: Some(colors).filter(|&c| c >= 0),
+                    truecolor,
+                })
+            },
+        )
+    }
+
+    #[test]
+    fn test_detect() {
+        use ColorDepth::*;
+        let xterm = [("TERM", "xterm-256color")];
+        assert_eq!(run(true, &xterm, None), Some(Ansi256));
+        assert_eq!(run(true, &xterm, Some((8, false))), Some(Ansi16));
+        assert_eq!(run(true, &xterm, Some((256, true))), Some(TrueColor));
+        assert_eq!(run(true, &xterm, Some((0x1000000, false))), Some(TrueColor));
+        assert_eq!(run(true, &xterm, Some((-1, false))), None);
+        assert_eq!(run(false, &xterm, None), None);
+        assert_eq!(
+            run(true, &[("TERM", "xterm-direct")], None),
+            Some(TrueColor)
+        );
+        assert_eq!(run(true, &[("TERM", "vt100")], None), Some(Ansi16));
+        assert_eq!(run(true, &[("TERM", "dumb")], None), None);
+        assert_eq!(run(true, &[], None), None);
+        assert_eq!(run(true, &[("WT_SESSION", "x")], None), Some(TrueColor));
+        let truecolor = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor")];
+        assert_eq!(run(true, &truecolor, Some((256, false))), Some(TrueColor));
+    }
+
+    #[test]
+    fn test_overrides() {
+        use ColorDepth::*;
+        let with = |extra: (&'static str, &'static str)| [("TERM", "xterm-256color"), extra];
+        assert_eq!(run(true, &with(("NO_COLOR", "1")), None), None);
+        // An empty `NO_COLOR` is ignored.
+        assert_eq!(run(true, &with(("NO_COLOR", "")), None), Some(Ansi256));
+        assert_eq!(run(true, &with(("CLICOLOR", "0")), None), None);
+        assert_eq!(run(false, &with(("FORCE_COLOR", "1")), None), Some(Ansi16));
+        assert_eq!(
+            run(true, &with(("FORCE_COLOR", "true")), None),
+            Some(Ansi16)
+        );
+        assert_eq!(run(false, &with(("FORCE_COLOR", "2")), None), Some(Ansi256));
+        assert_eq!(
+            run(false, &with(("FORCE_COLOR", "yes")), None),
+            Some(Ansi256)
+        );
+        assert_eq!(
+            run(false, &with(("FORCE_COLOR", "3")), None),
+            Some(TrueColor)
+        );
+        assert_eq!(run(true, &with(("FORCE_COLOR", "0")), None), None);
+        assert_eq!(
+            run(false, &with(("CLICOLOR_FORCE", "1")), None),
+            Some(Ansi256)
+        );
+        assert_eq!(run(false, &with(("CLICOLOR_FORCE", "0")), None), None);
+        assert_eq!(run(false, &[("FORCE_COLOR", "")], None), Some(Ansi16));
+        assert_eq!(
+            run(true, &[("NO_COLOR", "1"), ("FORCE_COLOR", "1")], None),
+            None
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Picking palette entries that are as far apart as possible, for
+//! categorical data (chart series, per-user nick colors, and so on).
+//!
+//! This is a greedy farthest-point selection in Oklab: after the first, each
+//! entry is the one furthest from its closest already-picked entry (and from
+//! the background, if one is given). It isn't optimal for any particular
+//! `n`, but every prefix is a good selection on its own, so growing the
+//! number of series doesn't reshuffle the colors of the existing ones. The
+//! order only depends on the palette tables, so it's the same everywhere.
+//!
+//! ```
+//! use termpal::distinct::Selector;
+//!
+//! let mut series = [0; 6];
+//! let n = Selector::new()
+//!     .greys(false)
+//!     .background((0x1e, 0x1e, 0x2e), 0.2)
+//!     .fill_ansi256(&mut series);
+//! assert_eq!(n, 6);
+//! for (i, index) in series.iter().enumerate() {
+//!     println!("\x1b[38;5;{index}mseries {i}\x1b[0m");
+//! }
+//! ```
+use crate::imp::oklab::OkLab;
+use crate::imp::tab::{ANSI256_RGB, LAB_PALETTE_ANSI256};
+use crate::Background;
+
+type Rgb = (u8, u8, u8);
+
+/// Selects maximally distinct palette entries. See the [module
+/// docs](self).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selector {
+    greys: bool,
+    background: Option<(Rgb, f32)>,
+}
+
+impl Selector {
+    /// A selector over the whole palette (`16..=255`), greys included.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            greys: true,
+            background: None,
+        }
+    }
+
+    /// Whether to consider greys (entries with equal red, green and blue),
+    /// which are distinct enough in lightness, but make poor categories.
+    #[inline]
+    pub const fn greys(mut self, include: bool) -> Self {
+        self.greys = include;
+        self
+    }
+
+    /// Leave out entries within `min_distance` (in Oklab units) of
+    /// `background`, and prefer entries far from it.
+    ///
+    /// As with [`Background`], a palette index is resolved with
+    /// [`ansi256_to_rgb`](crate::ansi256_to_rgb), even when selecting from
+    /// the 88-color palette.
+    #[inline]
+    pub fn background(mut self, background: impl Into<Background>, min_distance: f32) -> Self {
+        self.background = Some((background.into().to_rgb(), min_distance));
+        self
+    }
+
+    /// Fill `out` with distinct 256-color palette indices, in order. Returns
+    /// how many were written, which is less than `out.len()` if there aren't
+    /// that many entries left after excluding greys and the background.
+    pub fn fill_ansi256(&self, out: &mut [u8]) -> usize {
+        self.select(&LAB_PALETTE_ANSI256, &ANSI256_RGB, out)
+    }
+
+    /// The same as [`fill_ansi256`](Self::fill_ansi256), for the 88-color
+    /// palette (`16..88`).
+    #[cfg(feature = "88color")]
+    pub fn fill_ansi88(&self, out: &mut [u8]) -> usize {
+        use crate::imp::tab::{ANSI88_RGB, LAB_PALETTE_ANSI88};
+        self.select(&LAB_PALETTE_ANSI88, &ANSI88_RGB, out)
+    }
+
+    /// The same as [`fill_ansi256`](Self::fill_ansi256), returning a `Vec` of
+    /// up to `n` indices.
+    #[cfg(feature = "alloc")]
+    pub fn ansi256(&self, n: usize) -> alloc::vec::Vec<u8> {
+        let mut out = alloc::vec![0; n.min(LAB_PALETTE_ANSI256.len())];
+        let len = self.fill_ansi256(&mut out);
+        out.truncate(len);
+        out
+    }
+
+    /// `palette[i]` and `rgb[i]` are entry `i + 16`.
+    fn select(&self, palette: &[OkLab], rgb: &[Rgb], out: &mut [u8]) -> usize {
+        debug_assert!(palette.len() <= 240 && palette.len() == rgb.len());
+        // The squared distance from each entry to the closest thing it needs
+        // to be far from, or `None` if it's picked or excluded.
+        let mut nearest = [None::<f32>; 240];
+        let bg = self
+            .background
+            .map(|((r, g, b), min)| (OkLab::from_srgb8(r, g, b), min));
+        for (i, lab) in palette.iter().enumerate() {
+            let (r, g, b) = rgb[i];
+            if !self.greys && r == g && g == b {
+                continue;
+            }
+            nearest[i] = match bg {
+                Some((bg, min)) => Some(lab.dist_sq(&bg)).filter(|&d| d >= min * min),
+                // With nothing to be far from, start with the most colorful.
+                None => Some(lab.a * lab.a + lab.b * lab.b),
+            };
+        }
+
+        let mut len = 0;
+        for slot in out.iter_mut() {
+            let mut best = None::<(f32, usize)>;
+            for (i, d) in nearest[..palette.len()].iter().enumerate() {
+                if let Some(d) = *d {
+                    if best.is_none_or(|(bd, _)| d > bd) {
+                        best = Some((d, i));
+                    }
+                }
+            }
+            let Some((_, picked)) = best else {
+                break;
+            };
+            *slot = picked as u8 + 16;
+            len += 1;
+            nearest[picked] = None;
+            // The first pick replaces the chroma placeholder, if that's what
+            // was there.
+            let first = len == 1 && bg.is_none();
+            for (i, d) in nearest[..palette.len()].iter_mut().enumerate() {
+                if let Some(d) = d {
+                    let to_picked = palette[i].dist_sq(&palette[picked]);
+                    *d = if first { to_picked } else { d.min(to_picked) };
+                }
+            }
+        }
+        len
+    }
+}
+
+impl Default for Selector {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ansi256_to_rgb;
+
+    fn lab(index: u8) -> OkLab {
+        let (r, g, b) = ansi256_to_rgb(index);
+        OkLab::from_srgb8(r, g, b)
+    }
+
+    fn min_dist(indices: &[u8]) -> f32 {
+        let mut min = f32::MAX;
+        for (i, &a) in indices.iter().enumerate() {
+            for &b in &indices[..i] {
+                min = min.min(lab(a).dist_sq(&lab(b)));
+            }
+        }
+        min
+    }
+
+    #[test]
+    fn test_select() {
+        let mut out = [0; 12];
+        assert_eq!(Selector::new().fill_ansi256(&mut out), 12);
+        // Distinct, with prefixes that agree.
+        for (i, a) in out.iter().enumerate() {
+            assert!(!out[..i].contains(a), "{out:?}");
+        }
+        let mut short = [0; 5];
+        Selector::new().fill_ansi256(&mut short);
+        assert_eq!(short, out[..5]);
+        // A better spread than just taking evenly spaced entries.
+        let naive: [u8; 12] = core::array::from_fn(|i| 16 + i as u8 * 20);
+        assert!(min_dist(&out) > 1.5 * min_dist(&naive));
+
+        // Asking for more than there are.
+        let mut all = [0; 300];
+        assert_eq!(Selector::new().fill_ansi256(&mut all), 240);
+        assert_eq!(all[240..], [0; 60]);
+        let mut sorted = all[..240].to_vec();
+        sorted.sort_unstable();
+        assert!(sorted.iter().copied().eq(16..=255));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let mut out = [0; 20];
+        Selector::new().greys(false).fill_ansi256(&mut out);
+        for &i in &out {
+            let (r, g, b) = ansi256_to_rgb(i);
+            assert!(!(r == g && g == b), "{i}");
+        }
+        let mut all = [0; 240];
+        assert_eq!(
+            Selector::new().greys(false).fill_ansi256(&mut all),
+            240 - 30
+        );
+
+        // Nothing close to a dark background, and the first pick is as far
+        // from it as possible.
+        let bg = (0x1e, 0x1e, 0x2e);
+        let sel = Selector::new().background(bg, 0.3);
+        let n = sel.fill_ansi256(&mut all);
+        let bg_lab = OkLab::from_srgb8(bg.0, bg.1, bg.2);
+        assert!(all[..n].iter().all(|&i| lab(i).dist_sq(&bg_lab) >= 0.09));
+        assert!((16..=255).all(|i| lab(i).dist_sq(&bg_lab) <= lab(all[0]).dist_sq(&bg_lab)));
+        assert!(n < 240);
+
+        // Indices work too.
+        let mut a = [0; 8];
+        let mut b = [0; 8];
+        Selector::new().background(231, 0.2).fill_ansi256(&mut a);
+        Selector::new()
+            .background((0xff, 0xff, 0xff), 0.2)
+            .fill_ansi256(&mut b);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    #[cfg(feature = "88color")]
+    fn test_select_88() {
+        let mut out = [0; 80];
+        let n = Selector::new().fill_ansi88(&mut out);
+        assert_eq!(n, 72);
+        let mut sorted = out[..n].to_vec();
+        sorted.sort_unstable();
+        assert!(sorted.iter().copied().eq(16..88));
+    }
+}
@@ -0,0 +1,259 @@
+//! Perceptually even gradients, quantized to the 256-color palette.
+//!
+//! A [`Gradient`] interpolates between evenly spaced color stops in Oklab (or
+//! OkLCh), and then each step goes through the usual search. For short ramps,
+//! such as heat map legends and progress bars, you'll usually want
+//! [`Gradient::distinct`], which nudges steps that would land on an entry
+//! that's already used onto the next best one, so that every step is visibly
+//! different.
+//!
+//! ```
+//! use termpal::gradient::{runs, Gradient};
+//!
+//! let green_to_red = [(0x2e, 0xcc, 0x40), (0xff, 0xdc, 0x00), (0xff, 0x41, 0x36)];
+//! let mut bar = [0; 40];
+//! Gradient::new(&green_to_red).fill_ansi256(&mut bar);
+//! for (index, len) in runs(&bar) {
+//!     print!("\x1b[48;5;{index}m{:len$}", "");
+//! }
+//! println!("\x1b[0m");
+//! ```
+use crate::color::{lerp, OkLab, OkLch};
+use crate::imp::fallback::nearest_filtered;
+use crate::imp::tab::LAB_PALETTE_ANSI256;
+
+type Rgb = (u8, u8, u8);
+
+/// Which color space to interpolate in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Space {
+    /// Straight lines in Oklab. This never goes through colors that aren't
+    /// "between" the stops, but can look a bit washed out in the middle when
+    /// the stops have very different hues.
+    #[default]
+    Oklab,
+    /// Lightness, chroma and hue are interpolated separately (with the hue
+    /// going the short way around), which keeps the middle saturated.
+    OkLch,
+}
+
+/// A gradient through some colors, spaced evenly from `t = 0.0` to `1.0`.
+#[derive(Clone, Copy, Debug)]
+pub struct Gradient<'a> {
+    stops: &'a [Rgb],
+    space: Space,
+    distinct: bool,
+}
+
+impl<'a> Gradient<'a> {
+    /// A gradient through `stops`, in [`Space::Oklab`].
+    ///
+    /// # Panics
+    ///
+    /// If `stops` is empty.
+    #[track_caller]
+    pub const fn new(stops: &'a [Rgb]) -> Self {
+        assert!(!stops.is_empty(), "a gradient needs at least one stop");
+        Self {
+            stops,
+            space: Space::Oklab,
+            distinct: false,
+        }
+    }
+
+    /// Which color space to interpolate in.
+    #[inline]
+    pub const fn space(mut self, space: Space) -> Self {
+        self.space = space;
+        self
+    }
+
+    /// Whether to nudge steps so that no palette entry is used twice
+    /// (defaults to `false`).
+    ///
+    /// There are only 240 entries to go around, so for longer ramps than that
+    /// this only keeps consecutive steps distinct.
+    #[inline]
+    pub const fn distinct(mut self, distinct: bool) -> Self {
+        self.distinct = distinct;
+        self
+    }
+
+    /// The (unquantized) color at `t`, which is clamped to `0.0..=1.0`.
+    pub fn at(&self, t: f32) -> OkLab {
+        let segments = self.stops.len() - 1;
+        if segments == 0 {
+            return OkLab::from_rgb(self.stops[0]);
+        }
+        let pos = t.clamp(0.0, 1.0) * segments as f32;
+        let i = (pos as usize).min(segments - 1);
+        let (a, b) = (self.stops[i], self.stops[i + 1]);
+        let t = pos - i as f32;
+        match self.space {
+            Space::Oklab => OkLab::from_rgb(a).mix(OkLab::from_rgb(b), t),
+            Space::OkLch => lerp_lch(OkLch::from_rgb(a), OkLch::from_rgb(b), t).to_lab(),
+        }
+    }
+
+    /// Quantize `out.len()` evenly spaced steps (including both ends) into
+    /// `out`.
+    pub fn fill_ansi256(&self, out: &mut [u8]) {
+        let n = out.len();
+        for i in 0..n {
+            let t = if n == 1 {
+                0.0
+            } else {
+                i as f32 / (n - 1) as f32
+            };
+            let lab = self.at(t).clip_to_gamut();
+            let mut index = lab.nearest_ansi256();
+            if self.distinct {
+                let used = if n <= 240 {
+                    &out[..i]
+                } else {
+                    &out[i.saturating_sub(1)..i]
+                };
+                if used.contains(&index) {
+                    index = nearest_excluding(lab, used).unwrap_or(index);
+                }
+            }
+            out[i] = index;
+        }
+    }
+
+    /// The same as [`fill_ansi256`](Self::fill_ansi256), returning a `Vec` of
+    /// `steps` indices.
+    #[cfg(feature = "alloc")]
+    pub fn ansi256(&self, steps: usize) -> alloc::vec::Vec<u8> {
+        let mut out = alloc::vec![0; steps];
+        self.fill_ansi256(&mut out);
+        out
+    }
+}
+
+fn lerp_lch(a: OkLch, b: OkLch, t: f32) -> OkLch {
+    // Greys don't really have a hue, so use the other one's.
+    let (ha, hb) = match (a.c < 1e-4, b.c < 1e-4) {
+        (true, false) => (b.h, b.h),
+        (false, true) => (a.h, a.h),
+        _ => (a.h, b.h),
+    };
+    let mut dh = hb - ha;
+    if dh > 180.0 {
+        dh -= 360.0;
+    } else if dh < -180.0 {
+        dh += 360.0;
+    }
+    let h = ha + dh * t;
+    OkLch {
+        l: lerp(a.l, b.l, t),
+        c: lerp(a.c, b.c, t),
+        h: if h < 0.0 { h + 360.0 } else { h % 360.0 },
+    }
+}
+
+/// The nearest palette entry to `lab` that isn't in `used`.
+fn nearest_excluding(lab: OkLab, used: &[u8]) -> Option<u8> {
+    nearest_filtered(lab, &LAB_PALETTE_ANSI256, |i, _| {
+        !used.contains(&(i as u8 + 16))
+    })
+    .map(|i| i as u8 + 16)
+}
+
+/// Split `indices` into runs of the same index, as `(index, length)`, so that
+/// each run can be drawn with a single SGR sequence.
+///
+/// ```
+/// let runs: Vec<_> = termpal::gradient::runs(&[1, 1, 2, 3, 3, 3]).collect();
+/// assert_eq!(runs, [(1, 2), (2, 1), (3, 3)]);
+/// ```
+#[inline]
+pub fn runs(indices: &[u8]) -> Runs<'_> {
+    Runs { rest: indices }
+}
+
+/// The iterator returned by [`runs`].
+#[derive(Clone, Debug)]
+pub struct Runs<'a> {
+    rest: &'a [u8],
+}
+
+impl Iterator for Runs<'_> {
+    type Item = (u8, usize);
+
+    fn next(&mut self) -> Option<(u8, usize)> {
+        let &first = self.rest.first()?;
+        let len = self.rest.iter().take_while(|&&i| i == first).count();
+        self.rest = &self.rest[len..];
+        Some((first, len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gradient() {
+        let stops = [(0, 0, 0), (0xff, 0xff, 0xff)];
+        let g = Gradient::new(&stops);
+        assert_eq!(g.at(0.0).to_rgb(), (0, 0, 0));
+        assert_eq!(g.at(1.0).to_rgb(), (0xff, 0xff, 0xff));
+        // Even in lightness, not in sRGB.
+        assert!((g.at(0.5).l - 0.5).abs() < 1e-6);
+
+        let mut out = [0; 5];
+        g.fill_ansi256(&mut out);
+        assert_eq!((out[0], out[4]), (16, 231));
+        assert!(out
+            .windows(2)
+            .all(|w| crate::ansi256_to_rgb(w[0]).0 < crate::ansi256_to_rgb(w[1]).0));
+
+        // Single stops and steps don't fall over.
+        let one = Gradient::new(&stops[1..]);
+        one.fill_ansi256(&mut out);
+        assert_eq!(out, [231; 5]);
+        let mut single = [0];
+        g.fill_ansi256(&mut single);
+        assert_eq!(single, [16]);
+    }
+
+    #[test]
+    fn test_distinct() {
+        // Close enough together that the plain search repeats entries.
+        let stops = [(0x30, 0x60, 0x90), (0x40, 0x70, 0xa0)];
+        let mut plain = [0; 6];
+        Gradient::new(&stops).fill_ansi256(&mut plain);
+        assert!(plain.windows(2).any(|w| w[0] == w[1]), "{plain:?}");
+
+        let mut out = [0; 6];
+        Gradient::new(&stops).distinct(true).fill_ansi256(&mut out);
+        for (i, a) in out.iter().enumerate() {
+            assert!(!out[..i].contains(a), "{out:?}");
+        }
+        assert_eq!(out[0], plain[0]);
+
+        // Longer than the palette, so only neighbors differ.
+        let mut long = [0; 300];
+        Gradient::new(&stops).distinct(true).fill_ansi256(&mut long);
+        assert!(long.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_lch() {
+        // Red to blue the short way around, through purple (rather than
+        // through green), keeping the chroma up.
+        let stops = [(0xff, 0, 0), (0, 0, 0xff)];
+        let g = Gradient::new(&stops).space(Space::OkLch);
+        let mid = g.at(0.5).to_lch();
+        assert!(mid.h > 264.0 || mid.h < 29.0, "{mid:?}");
+        let lab_mid = Gradient::new(&stops).at(0.5).to_lch();
+        assert!(mid.c > lab_mid.c, "{mid:?} {lab_mid:?}");
+
+        // Grey to a color keeps that color's hue.
+        let stops = [(0x80, 0x80, 0x80), (0, 0x80, 0)];
+        let g = Gradient::new(&stops).space(Space::OkLch);
+        let green = OkLch::from_rgb((0, 0x80, 0)).h;
+        assert!((g.at(0.5).to_lch().h - green).abs() < 0.5);
+    }
+}
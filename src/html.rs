@@ -0,0 +1,479 @@
+//! Converting terminal output with SGR colors to HTML.
+//!
+//! A [`Converter`] wraps an [`io::Write`], and turns the text written to it
+//! into HTML, with `<span>`s for the colors and attributes set by SGR
+//! sequences. This is meant for things like showing build logs in a browser,
+//! so other escape sequences (cursor movement, window titles, ...) are dropped,
+//! as there's nothing sensible to turn them into.
+//!
+//! Indexed colors are resolved using [`ansi256_to_rgb`], or a theme, as the
+//! first 16 are usually customized, and the defaults are fairly garish. The
+//! output is a fragment, and doesn't include the `<pre>` (or similar) that
+//! it'll need to go in.
+//!
+//! As with a [`Rewriter`](crate::rewrite::Rewriter), output the underlying
+//! writer fails to take is held, and written (or the error reported) by the
+//! next call, so retrying after an error never writes anything twice.
+//!
+//! [`ansi256_to_rgb`]: crate::ansi256_to_rgb
+use crate::palette::Palette;
+use crate::rewrite::{write_pending, Chunk, Scanner};
+use crate::sgr::{self, Attrs, Color, Style};
+use core::fmt::Write as _;
+use std::io::{self, Write};
+use std::string::String;
+use std::vec::Vec;
+
+type Rgb = (u8, u8, u8);
+
+/// How a [`Converter`] applies styles to its output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Styling {
+    /// Use `style` attributes, so the output doesn't need a stylesheet.
+    #[default]
+    Inline,
+    /// Use classes (`ansi-fg-1`, `ansi-bold`, ...) for the named colors and
+    /// the attributes, so they can be themed with CSS. Other colors still use
+    /// `style` attributes. [`Converter::write_css`] writes a stylesheet for
+    /// the classes.
+    Classes,
+}
+
+/// An [`io::Write`] adapter that converts ANSI-colored text to HTML.
+///
+/// ```
+/// use std::io::Write;
+/// use termpal::html::Converter;
+///
+/// let mut html = Converter::new(Vec::new());
+/// html.write_all(b"\x1b[1;31mbold red\x1b[0m <3").unwrap();
+/// let out = String::from_utf8(html.into_inner().unwrap()).unwrap();
+/// assert_eq!(
+///     out,
+///     r#"<span style="color:#ff0000;font-weight:bold">bold red</span> &lt;3"#,
+/// );
+/// ```
+///
+/// Spans are only opened just before text is written, and the last one is
+/// closed by [`finish`](Converter::finish) (or
+/// [`into_inner`](Converter::into_inner)), which must be called for the
+/// output to be well-formed.
+#[derive(Debug)]
+pub struct Converter<W: Write> {
+    scanner: Scanner,
+    out: Output<W>,
+}
+
+impl<W: Write> Converter<W> {
+    #[inline]
+    pub fn new(inner: W) -> Self {
+        Self {
+            scanner: Scanner::default(),
+            out: Output {
+                inner,
+                styling: Styling::Inline,
+                theme: None,
+                bold_is_bright: true,
+                default_colors: None,
+                style: Style::new(),
+                open: None,
+                buf: String::new(),
+                pending: Vec::new(),
+            },
+        }
+    }
+
+    #[inline]
+    pub fn styling(mut self, styling: Styling) -> Self {
+        self.out.styling = styling;
+        self
+    }
+
+    /// Use `theme` for the indexed colors it has entries for (usually just the
+    /// 16 named colors), instead of [`ansi256_to_rgb`](crate::ansi256_to_rgb).
+    #[inline]
+    pub fn theme(mut self, theme: Palette) -> Self {
+        self.out.theme = Some(theme);
+        self
+    }
+
+    /// Whether bold text with one of the first 8 named colors uses the bright
+    /// version of the color, as many terminals do. This is on by default.
+    #[inline]
+    pub fn bold_is_bright(mut self, on: bool) -> Self {
+        self.out.bold_is_bright = on;
+        self
+    }
+
+    /// The default foreground and background colors. These are only needed
+    /// for reverse video, which has to swap them, and are named colors `7`
+    /// and `0` by default.
+    #[inline]
+    pub fn default_colors(mut self, fg: (u8, u8, u8), bg: (u8, u8, u8)) -> Self {
+        self.out.default_colors = Some((fg, bg));
+        self
+    }
+
+    /// Write CSS rules for the classes used with [`Styling::Classes`], for
+    /// the current theme.
+    pub fn write_css<F: core::fmt::Write + ?Sized>(&self, out: &mut F) -> core::fmt::Result {
+        for n in 0..16 {
+            writeln!(out, ".ansi-fg-{n}{{color:{}}}", Hex(self.out.rgb(n)))?;
+        }
+        for n in 0..16 {
+            writeln!(
+                out,
+                ".ansi-bg-{n}{{background-color:{}}}",
+                Hex(self.out.rgb(n))
+            )?;
+        }
+        let (fg, bg) = self.out.default_colors();
+        writeln!(out, ".ansi-fg-reverse{{color:{}}}", Hex(bg))?;
+        writeln!(out, ".ansi-bg-reverse{{background-color:{}}}", Hex(fg))?;
+        out.write_str(concat!(
+            ".ansi-bold{font-weight:bold}\n",
+            ".ansi-dim{opacity:0.5}\n",
+            ".ansi-italic{font-style:italic}\n",
+            ".ansi-underline{text-decoration-line:underline}\n",
+            ".ansi-strikethrough{text-decoration-line:line-through}\n",
+            ".ansi-underline.ansi-strikethrough{text-decoration-line:underline line-through}\n",
+            ".ansi-hidden{visibility:hidden}\n",
+        ))
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.out.inner
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out.inner
+    }
+
+    /// Close the open span, if any. Any incomplete escape sequence is
+    /// dropped, and the style is reset.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.scanner.finish(|_| Ok(()))?;
+        self.out.style = Style::new();
+        self.out.close();
+        write_pending(&mut self.out.pending, &mut self.out.inner)
+    }
+
+    /// [`finish`](Self::finish), and return the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        Ok(self.out.inner)
+    }
+}
+
+impl<W: Write> Write for Converter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let out = &mut self.out;
+        write_pending(&mut out.pending, &mut out.inner)?;
+        let _ = self.scanner.feed(buf, |chunk| {
+            match chunk {
+                Chunk::Text(text) => out.text(text),
+                Chunk::Sgr { params, .. } => {
+                    // Anything after an error is ignored, but the parameters
+                    // before it still apply, as they would in a terminal.
+                    for event in sgr::parse(params).map_while(Result::ok) {
+                        out.style.apply(event);
+                    }
+                }
+                Chunk::Escape(_) => {}
+            }
+            Ok(())
+        });
+        // `buf` is ours now, so an error is left for the next call.
+        let _ = write_pending(&mut out.pending, &mut out.inner);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        write_pending(&mut self.out.pending, &mut self.out.inner)?;
+        self.out.inner.flush()
+    }
+}
+
+/// A color in the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Paint {
+    Named(u8),
+    Rgb(u8, u8, u8),
+    /// The default color of the other layer, for reverse video.
+    Reverse,
+}
+
+/// The (non-empty) styling of a `<span>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Span {
+    fg: Option<Paint>,
+    bg: Option<Paint>,
+    underline_color: Option<(u8, u8, u8)>,
+    /// Never includes `REVERSE` (which is handled by swapping the colors) or
+    /// `BLINK` (which isn't supported).
+    attrs: Attrs,
+}
+
+/// Everything but the [`Scanner`], so it can be borrowed separately.
+#[derive(Debug)]
+struct Output<W> {
+    inner: W,
+    styling: Styling,
+    theme: Option<Palette>,
+    bold_is_bright: bool,
+    /// The default foreground and background.
+    default_colors: Option<(Rgb, Rgb)>,
+    /// The current style, as set by the SGR sequences.
+    style: Style,
+    open: Option<Span>,
+    buf: String,
+    /// Output `inner` hasn't taken yet.
+    pending: Vec<u8>,
+}
+
+impl<W> Output<W> {
+    fn text(&mut self, text: &[u8]) {
+        let span = self.span();
+        if span != self.open {
+            self.close();
+            if let Some(span) = span {
+                self.buf.clear();
+                let _ = self.write_open(&span);
+                self.pending.extend_from_slice(self.buf.as_bytes());
+                self.open = Some(span);
+            }
+        }
+        let mut rest = text;
+        while let Some(i) = rest.iter().position(|b| b"&<>".contains(b)) {
+            self.pending.extend_from_slice(&rest[..i]);
+            self.pending.extend_from_slice(match rest[i] {
+                b'&' => b"&amp;",
+                b'<' => b"&lt;",
+                _ => b"&gt;",
+            });
+            rest = &rest[i + 1..];
+        }
+        self.pending.extend_from_slice(rest);
+    }
+
+    fn close(&mut self) {
+        if self.open.take().is_some() {
+            self.pending.extend_from_slice(b"</span>");
+        }
+    }
+
+    fn rgb(&self, n: u8) -> (u8, u8, u8) {
+        self.theme
+            .as_ref()
+            .and_then(|t| t.get(n))
+            .unwrap_or_else(|| crate::ansi256_to_rgb(n))
+    }
+
+    fn default_colors(&self) -> (Rgb, Rgb) {
+        self.default_colors
+            .unwrap_or_else(|| (self.rgb(7), self.rgb(0)))
+    }
+
+    /// The span for the current style, or `None` if no span is needed.
+    fn span(&self) -> Option<Span> {
+        let style = &self.style;
+        let mut fg = style.fg;
+        if self.bold_is_bright && style.attrs.contains(Attrs::BOLD) {
+            if let Some(Color::Indexed(n @ 0..=7)) = fg {
+                fg = Some(Color::Indexed(n + 8));
+            }
+        }
+        let paint = |c: Option<Color>| match c? {
+            Color::Indexed(n) if n < 16 => Some(Paint::Named(n)),
+            Color::Indexed(n) => {
+                let (r, g, b) = self.rgb(n);
+                Some(Paint::Rgb(r, g, b))
+            }
+            Color::Rgb(r, g, b) => Some(Paint::Rgb(r, g, b)),
+            Color::Default => None,
+        };
+        let (fg, bg) = match style.attrs.contains(Attrs::REVERSE) {
+            false => (paint(fg), paint(style.bg)),
+            true => (
+                Some(paint(style.bg).unwrap_or(Paint::Reverse)),
+                Some(paint(fg).unwrap_or(Paint::Reverse)),
+            ),
+        };
+        let underline_color = match style.underline_color {
+            Some(Color::Indexed(n)) => Some(self.rgb(n)),
+            Some(Color::Rgb(r, g, b)) => Some((r, g, b)),
+            _ => None,
+        };
+        let attrs = style.attrs.difference(Attrs::REVERSE | Attrs::BLINK);
+        let span = Span {
+            fg,
+            bg,
+            underline_color,
+            attrs,
+        };
+        let empty = fg.is_none() && bg.is_none() && underline_color.is_none() && attrs.is_empty();
+        (!empty).then_some(span)
+    }
+
+    /// Write the opening tag for `span` to `self.buf`.
+    fn write_open(&mut self, span: &Span) -> core::fmt::Result {
+        let classes = self.styling == Styling::Classes;
+        let (default_fg, default_bg) = self.default_colors();
+        let mut class = String::new();
+        let mut css = String::new();
+        let layers = [
+            (span.fg, "fg", "color", default_bg),
+            (span.bg, "bg", "background-color", default_fg),
+        ];
+        for (paint, layer, prop, reverse) in layers {
+            match paint {
+                Some(Paint::Named(n)) if classes => write!(class, " ansi-{layer}-{n}")?,
+                Some(Paint::Reverse) if classes => write!(class, " ansi-{layer}-reverse")?,
+                Some(Paint::Named(n)) => write!(css, "{prop}:{};", Hex(self.rgb(n)))?,
+                Some(Paint::Rgb(r, g, b)) => write!(css, "{prop}:{};", Hex((r, g, b)))?,
+                Some(Paint::Reverse) => write!(css, "{prop}:{};", Hex(reverse))?,
+                None => {}
+            }
+        }
+        let attrs = [
+            (Attrs::BOLD, "bold", "font-weight:bold;"),
+            (Attrs::DIM, "dim", "opacity:0.5;"),
+            (Attrs::ITALIC, "italic", "font-style:italic;"),
+            (Attrs::UNDERLINE, "underline", ""),
+            (Attrs::STRIKETHROUGH, "strikethrough", ""),
+            (Attrs::HIDDEN, "hidden", "visibility:hidden;"),
+        ];
+        for (attr, name, decl) in attrs {
+            if span.attrs.contains(attr) {
+                if classes {
+                    write!(class, " ansi-{name}")?;
+                } else {
+                    css.push_str(decl);
+                }
+            }
+        }
+        if !classes {
+            let line = match (
+                span.attrs.contains(Attrs::UNDERLINE),
+                span.attrs.contains(Attrs::STRIKETHROUGH),
+            ) {
+                (true, true) => "underline line-through",
+                (true, false) => "underline",
+                (false, true) => "line-through",
+                (false, false) => "",
+            };
+            if !line.is_empty() {
+                write!(css, "text-decoration:{line};")?;
+            }
+        }
+        if let Some(c) = span.underline_color {
+            write!(css, "text-decoration-color:{};", Hex(c))?;
+        }
+
+        self.buf.push_str("<span");
+        if let Some(class) = class.strip_prefix(' ') {
+            write!(self.buf, r#" class="{class}""#)?;
+        }
+        if let Some(css) = css.strip_suffix(';') {
+            write!(self.buf, r#" style="{css}""#)?;
+        }
+        self.buf.push('>');
+        Ok(())
+    }
+}
+
+/// Formats a color as `#rrggbb`.
+struct Hex((u8, u8, u8));
+
+impl core::fmt::Display for Hex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (r, g, b) = self.0;
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec::Vec;
+
+    fn convert(c: Converter<Vec<u8>>, input: &[u8]) -> String {
+        let mut c = c;
+        // Byte by byte, to make sure nothing depends on how the input is split.
+        for b in input.chunks(1) {
+            c.write_all(b).unwrap();
+        }
+        String::from_utf8(c.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_inline() {
+        let c = || Converter::new(Vec::new());
+        assert_eq!(convert(c(), b"a & b\x1b[0m\n"), "a &amp; b\n");
+        assert_eq!(
+            convert(
+                c(),
+                b"\x1b[31ma\x1b[1mb\x1b[22;4;38;5;196mc\x1b[39;24m d\x1b[m"
+            ),
+            concat!(
+                r#"<span style="color:#800000">a</span>"#,
+                r#"<span style="color:#ff0000;font-weight:bold">b</span>"#,
+                r#"<span style="color:#ff0000;text-decoration:underline">c</span> d"#,
+            )
+        );
+        // Reverse video, with and without colors set.
+        assert_eq!(
+            convert(c(), b"\x1b[7mx\x1b[38;2;1;2;3my\x1b[0m"),
+            concat!(
+                r#"<span style="color:#000000;background-color:#c0c0c0">x</span>"#,
+                r#"<span style="color:#000000;background-color:#010203">y</span>"#,
+            )
+        );
+        // Other sequences are dropped, and a span left open is closed.
+        assert_eq!(
+            convert(c(), b"\x1b]0;title\x07\x1b[2J\x1b[3;9mz\x1b[38;2"),
+            r#"<span style="font-style:italic;text-decoration:line-through">z</span>"#
+        );
+    }
+
+    #[test]
+    fn test_classes_and_theme() {
+        let theme = Palette::from_rgb(&[(1, 1, 1), (2, 2, 2), (3, 3, 3)]);
+        let c = Converter::new(Vec::new())
+            .styling(Styling::Classes)
+            .theme(theme)
+            .bold_is_bright(false);
+        let mut css = String::new();
+        c.write_css(&mut css).unwrap();
+        assert!(css.contains(".ansi-fg-1{color:#020202}\n"));
+        assert!(css.contains(".ansi-bg-reverse{background-color:#c0c0c0}\n"));
+        assert_eq!(
+            convert(c, b"\x1b[1;31;48;5;2;58;5;16mx\x1b[0;7my"),
+            concat!(
+                r#"<span class="ansi-fg-1 ansi-bg-2 ansi-bold" style="text-decoration-color:#000000">x</span>"#,
+                r#"<span class="ansi-fg-reverse ansi-bg-reverse">y</span>"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_errors() {
+        use crate::rewrite::test::{write_retrying, Flaky};
+
+        let input = b"a<\x1b[1;31mbcdef\x1b[0mg&\x1b]0;t\x07h\x1b[4mi";
+        let want = convert(Converter::new(Vec::new()), input);
+        for fail_at in 0..12 {
+            let mut c = Converter::new(Flaky::new(fail_at));
+            write_retrying(&mut c, input);
+            // Make sure it actually failed.
+            assert!(c.get_ref().calls > fail_at);
+            // The span left open is closed by `finish`, which can fail too.
+            while c.finish().is_err() {}
+            let out = String::from_utf8(c.get_ref().out.clone()).unwrap();
+            assert_eq!(out, want, "failing call {fail_at}");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Assigning a whole set of colors to distinct palette entries at once, as a
+//! min-cost bipartite matching (solved with the Hungarian algorithm).
+use super::oklab::OkLab;
+use super::tab::LAB_PALETTE_ANSI256;
+use alloc::vec::Vec;
+
+/// The number of palette entries that can be assigned, i.e. `16..=255`.
+const ENTRIES: usize = 240;
+
+/// Assign each of `colors` an index, and the squared Oklab distance to it.
+///
+/// The first 240 distinct colors get distinct entries, minimizing the total
+/// squared distance. Repeated colors share an index, and any distinct colors
+/// past the 240th just get their nearest entry.
+pub(crate) fn assign_distinct_ansi256(colors: &[(u8, u8, u8)]) -> Vec<(u8, f32)> {
+    let mut distinct: Vec<(u8, u8, u8)> = Vec::new();
+    for &c in colors {
+        if distinct.len() < ENTRIES && !distinct.contains(&c) {
+            distinct.push(c);
+        }
+    }
+    let labs: Vec<OkLab> = distinct
+        .iter()
+        .map(|&(r, g, b)| OkLab::from_srgb8(r, g, b))
+        .collect();
+    let matched = hungarian(&labs);
+
+    colors
+        .iter()
+        .map(|&(r, g, b)| {
+            let lab = OkLab::from_srgb8(r, g, b);
+            let j = match distinct.iter().position(|&c| c == (r, g, b)) {
+                Some(i) => matched[i],
+                None => super::nearest_ansi256(r, g, b) as usize - 16,
+            };
+            (j as u8 + 16, lab.dist_sq(&LAB_PALETTE_ANSI256[j]))
+        })
+        .collect()
+}
+
+/// Match each of `rows` (at most `ENTRIES`) to a distinct palette entry,
+/// minimizing the sum of the squared distances. Returns the entry (as an
+/// index into `LAB_PALETTE_ANSI256`) for each row.
+///
+/// This is the O(n²m) version of the algorithm using potentials, with the
+/// usual 1-based indexing (column 0 is a sentinel).
+fn hungarian(rows: &[OkLab]) -> Vec<usize> {
+    let (n, m) = (rows.len(), ENTRIES);
+    debug_assert!(n <= m);
+    let cost = |i: usize, j: usize| f64::from(rows[i - 1].dist_sq(&LAB_PALETTE_ANSI256[j - 1]));
+
+    let mut u = [0.0f64; ENTRIES + 1];
+    let mut v = [0.0f64; ENTRIES + 1];
+    // `p[j]` is the row matched to column `j`, and `way[j]` the previous
+    // column on the augmenting path.
+    let mut p = [0usize; ENTRIES + 1];
+    let mut way = [0usize; ENTRIES + 1];
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = [f64::INFINITY; ENTRIES + 1];
+        let mut used = [false; ENTRIES + 1];
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let cur = cost(i0, j) - u[i0] - v[j];
+                if cur < minv[j] {
+                    minv[j] = cur;
+                    way[j] = j0;
+                }
+                if minv[j] < delta {
+                    delta = minv[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path.
+        while j0 != 0 {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut out = alloc::vec![0; n];
+    for j in 1..=m {
+        if p[j] != 0 {
+            out[p[j] - 1] = j - 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nearest_ansi256;
+
+    #[test]
+    fn test_assign() {
+        // An "error" and a "warning" that would both be 203.
+        let theme = [
+            (0xff, 0x5f, 0x5f),
+            (0xf0, 0x60, 0x60),
+            (0xff, 0x5f, 0x5f),
+            (0x00, 0x00, 0x00),
+        ];
+        assert_eq!(nearest_ansi256(0xf0, 0x60, 0x60), 203);
+        let out = assign_distinct_ansi256(&theme);
+        let indices: Vec<u8> = out.iter().map(|a| a.0).collect();
+        // Exact matches stay put, and the other one moves.
+        assert_eq!(indices[0], 203);
+        assert_ne!(indices[1], 203);
+        assert_eq!(indices[2], 203);
+        assert_eq!(indices[3], 16);
+        assert_eq!((out[0].1, out[3].1), (0.0, 0.0));
+        assert!(out[1].1 > 0.0);
+    }
+
+    #[test]
+    fn test_assign_optimal() {
+        // Compare against brute force on a small set of colors that all
+        // compete for the same few entries.
+        let colors = [
+            (0x80, 0x80, 0x70),
+            (0x84, 0x7c, 0x78),
+            (0x7c, 0x84, 0x78),
+            (0x88, 0x88, 0x88),
+        ];
+        let labs: Vec<OkLab> = colors
+            .iter()
+            .map(|&(r, g, b)| OkLab::from_srgb8(r, g, b))
+            .collect();
+        let total = |js: &[usize]| -> f32 {
+            js.iter()
+                .zip(&labs)
+                .map(|(&j, l)| l.dist_sq(&LAB_PALETTE_ANSI256[j]))
+                .sum()
+        };
+        let matched = hungarian(&labs);
+        let mut best = f32::MAX;
+        // The best assignment will only use entries near these colors.
+        let near: Vec<usize> = (0..ENTRIES)
+            .filter(|&j| {
+                labs.iter()
+                    .any(|l| l.dist_sq(&LAB_PALETTE_ANSI256[j]) < 0.01)
+            })
+            .collect();
+        for &a in &near {
+            for &b in &near {
+                for &c in &near {
+                    for &d in &near {
+                        let js = [a, b, c, d];
+                        if (1..4).all(|k| !js[..k].contains(&js[k])) {
+                            best = best.min(total(&js));
+                        }
+                    }
+                }
+            }
+        }
+        assert!(
+            (total(&matched) - best).abs() < 1e-6,
+            "{} {best}",
+            total(&matched)
+        );
+
+        // Every entry can be used once, and anything past that gets the
+        // nearest.
+        let all: Vec<(u8, u8, u8)> = (0..=255).map(|i| (i, i, 0)).collect();
+        let out = assign_distinct_ansi256(&all);
+        let mut first: Vec<u8> = out[..ENTRIES].iter().map(|a| a.0).collect();
+        first.sort_unstable();
+        first.dedup();
+        assert_eq!(first.len(), ENTRIES);
+        for (&(r, g, b), a) in all[ENTRIES..].iter().zip(&out[ENTRIES..]) {
+            assert_eq!(a.0, nearest_ansi256(r, g, b));
+        }
+    }
+}
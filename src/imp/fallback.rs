@@ -26,21 +26,35 @@ pub(crate) fn nearest_impl(v: OkLab, table: &[OkLab]) -> usize {
     let mut bi = 0;
     let mut bm = f32::MAX;
     for (i, c) in table.iter().enumerate() {
-        let m = euc_dist_sq(c, &v);
+        let m = c.dist_sq(&v);
         if m < bm {
             bm = m;
             bi = i;
         }
     }
-    return bi;
+    bi
+}
 
-    #[inline]
-    fn euc_dist_sq(a: &OkLab, b: &OkLab) -> f32 {
-        let dl = a.l - b.l;
-        let da = a.a - b.a;
-        let db = a.b - b.b;
-        (dl * dl) + (da * da) + (db * db)
+/// The same as [`nearest_impl`], only considering the entries for which
+/// `keep(i, &table[i])` is true, or `None` if there aren't any. `keep` is only
+/// called for entries that are nearer than the best so far, so it can be
+/// fairly expensive.
+#[inline]
+pub(crate) fn nearest_filtered(
+    v: OkLab,
+    table: &[OkLab],
+    mut keep: impl FnMut(usize, &OkLab) -> bool,
+) -> Option<usize> {
+    let mut best = None;
+    let mut bm = f32::MAX;
+    for (i, c) in table.iter().enumerate() {
+        let m = c.dist_sq(&v);
+        if m < bm && keep(i, c) {
+            bm = m;
+            best = Some(i);
+        }
     }
+    best
 }
 
 #[inline]
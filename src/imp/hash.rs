@@ -0,0 +1,110 @@
+//! Hashing keys (nicknames, hostnames, log sources...) onto palette entries
+//! that are readable against a background.
+//!
+//! Everything that decides the result is fixed here: the hash (32-bit
+//! FNV-1a), the candidate list, the order it's probed in, and the contrast
+//! thresholds. Changing any of them changes everybody's colors, so don't.
+use crate::contrast::apca_lc;
+
+/// The candidates: every entry with an OkLCh chroma of at least 0.08, in the
+/// order picked by `distinct::Selector::new().greys(false)`, so that light
+/// and dark (and different hues) are interleaved, and a key whose entry is
+/// unreadable on some background moves on to something quite different.
+///
+/// This is frozen, rather than computed, so that it can't change along with
+/// the selector or the palette tables.
+#[rustfmt::skip]
+const CANDIDATES: [u8; 176] = [
+    201,  46,  17,  94,  27,  90, 208,  37,  52, 228, 197, 140,  24,  50,  34,  93,
+     22, 124,  20, 149, 211,  29,  45, 163,  98,  32, 107, 213,  55,  41,  39, 167,
+    128, 155,  53, 125, 169, 100,  79, 220, 178, 171, 218,  61, 174,  88,  28,  69,
+    160, 206, 183, 157, 161, 204, 199,  48, 202,  57, 165,  18, 196, 111, 130, 131,
+    166, 133, 186, 112, 215, 135,  63, 172,  58, 226,  19,  74,  54, 177,  89,  21,
+     35,  25,  91, 126,  56, 136, 117,  92,  31,  87, 127,  97, 129, 162, 142,  26,
+     62, 104, 164,  99, 132, 134,  30,  72, 141, 170, 200, 176, 207,  44, 184, 205,
+    212, 175, 219, 198, 168, 209, 118,  64, 217, 106, 114,  85,  33, 122, 203, 173,
+     40, 192,  68, 190, 147, 105, 143, 210, 214, 222, 179,  42, 148, 120, 193,  70,
+     36, 113, 216,  71, 229, 150,  38,  83, 185,  75, 221, 154, 156, 158, 191,  76,
+     77, 227,  78, 115,  43,  80,  81, 119,  82, 121,  47,  84, 123,  49,  86,  51,
+];
+
+/// The APCA contrast (as an absolute Lc) a candidate needs against the
+/// background, tried in order until at least one candidate meets it. 60 is
+/// about the minimum for short bold labels, which is how names are usually
+/// drawn, and backgrounds that nothing meets that for (mid greys, mostly)
+/// fall back to the next.
+const MIN_LC: [f32; 4] = [60.0, 45.0, 30.0, 0.0];
+
+/// 32-bit FNV-1a.
+#[inline]
+pub(crate) fn fnv1a(key: &[u8]) -> u32 {
+    let mut h = 0x811c_9dc5u32;
+    for &b in key {
+        h ^= u32::from(b);
+        h = h.wrapping_mul(0x0100_0193);
+    }
+    h
+}
+
+pub(crate) fn hash_to_ansi256(key: &[u8], bg: (u8, u8, u8)) -> u8 {
+    let start = fnv1a(key) as usize % CANDIDATES.len();
+    for min in MIN_LC {
+        // Probe forward from the key's slot, so that a key keeps its color on
+        // every background it's readable on.
+        let found = (0..CANDIDATES.len())
+            .map(|i| CANDIDATES[(start + i) % CANDIDATES.len()])
+            .find(|&index| apca_lc(crate::ansi256_to_rgb(index), bg).abs() >= min);
+        if let Some(index) = found {
+            return index;
+        }
+    }
+    unreachable!("everything has at least 0 contrast")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fnv() {
+        // The reference test vectors.
+        assert_eq!(fnv1a(b""), 0x811c9dc5);
+        assert_eq!(fnv1a(b"a"), 0xe40c292c);
+        assert_eq!(fnv1a(b"foobar"), 0xbf9cf968);
+    }
+
+    #[test]
+    fn test_candidates() {
+        for (i, &a) in CANDIDATES.iter().enumerate() {
+            assert!(a >= 16 && !CANDIDATES[..i].contains(&a), "{a}");
+            let (r, g, b) = crate::ansi256_to_rgb(a);
+            assert!(!(r == g && g == b), "{a}");
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let names: [&[u8]; 6] = [b"alice", b"bob", b"carol", b"dave", b"eve", b""];
+        for bg in [
+            (0, 0, 0),
+            (0x1e, 0x1e, 0x2e),
+            (0xff, 0xff, 0xff),
+            (0xfd, 0xf6, 0xe3),
+        ] {
+            for name in names {
+                let index = hash_to_ansi256(name, bg);
+                assert!(CANDIDATES.contains(&index));
+                assert!(apca_lc(crate::ansi256_to_rgb(index), bg).abs() >= 60.0);
+            }
+        }
+        // A mid grey still gets something.
+        let index = hash_to_ansi256(b"alice", (0x77, 0x77, 0x77));
+        assert!(apca_lc(crate::ansi256_to_rgb(index), (0x77, 0x77, 0x77)).abs() >= 30.0);
+
+        // These must never change.
+        let dark: [u8; 6] = names.map(|n| hash_to_ansi256(n, (0, 0, 0)));
+        let light: [u8; 6] = names.map(|n| hash_to_ansi256(n, (0xff, 0xff, 0xff)));
+        assert_eq!(dark, [44, 45, 186, 51, 228, 226]);
+        assert_eq!(light, [198, 29, 135, 17, 90, 135]);
+    }
+}
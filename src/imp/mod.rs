@@ -28,10 +28,15 @@ pub(crate) mod cached {
     pub use crate::imp::nearest_ansi88_direct as nearest_ansi88;
 }
 
+#[cfg(feature = "alloc")]
+pub(crate) mod assign;
+
 #[allow(dead_code)]
 pub(crate) mod fallback;
 
+pub(crate) mod hash;
 pub(crate) mod oklab;
+pub(crate) mod pair;
 pub(crate) mod tab;
 
 #[cfg(all(
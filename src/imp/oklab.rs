@@ -7,6 +7,11 @@
 //! to CIE1976 ΔE*ab (that's fine though, it's not 1976 anymore, and that
 //! distance metric is no longer recommended).
 
+/// A color in [Oklab](https://bottosson.github.io/posts/oklab), which is
+/// what all of the nearest color searches measure distances in.
+///
+/// `l` is the lightness, from 0 (black) to 1 (white), and `a` and `b` are
+/// the green-red and blue-yellow axes.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(C)]
 pub struct OkLab {
@@ -27,12 +32,62 @@ impl OkLab {
         let r = srgb[r as usize];
         let g = srgb[g as usize];
         let b = srgb[b as usize];
-        let x = 0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b;
-        let y = 0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b;
-        let z = 0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b;
-        let l = oklab_cbrt(x);
-        let m = oklab_cbrt(y);
-        let s = oklab_cbrt(z);
+        let (x, y, z) = linear_to_lms(r, g, b);
+        Self::from_lms_cbrt(oklab_cbrt(x), oklab_cbrt(y), oklab_cbrt(z))
+    }
+
+    /// Convert from linear (not gamma-encoded) RGB. Components are clamped to
+    /// `0.0..=1.0` first, so this is fine to call with the result of blending
+    /// or averaging (which may have rounding error, or be tiny but nonzero).
+    #[inline]
+    pub(crate) fn from_linear(r: f32, g: f32, b: f32) -> Self {
+        let (x, y, z) = linear_to_lms(clamp01(r), clamp01(g), clamp01(b));
+        // Unlike `oklab_cbrt`, we can't assert that tiny inputs are exactly
+        // zero here.
+        let cbrt = |f: f32| -> f32 {
+            if f < CBRT_MIN {
+                0.0
+            } else {
+                oklab_do_cbrt(f.min(1.0))
+            }
+        };
+        Self::from_lms_cbrt(cbrt(x), cbrt(y), cbrt(z))
+    }
+
+    /// Convert to linear RGB. The result may be out of `0.0..=1.0` if the
+    /// color is outside of the sRGB gamut.
+    #[inline]
+    pub(crate) fn to_linear(self) -> (f32, f32, f32) {
+        let l = self.l + 0.3963377774 * self.a + 0.2158037573 * self.b;
+        let m = self.l - 0.1055613458 * self.a - 0.0638541728 * self.b;
+        let s = self.l - 0.0894841775 * self.a - 1.2914855480 * self.b;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+        (
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        )
+    }
+
+    /// Convert to sRGB, clamping each component if it's out of gamut.
+    #[inline]
+    pub(crate) fn to_srgb8(self) -> (u8, u8, u8) {
+        let (r, g, b) = self.to_linear();
+        (linear_to_srgb8(r), linear_to_srgb8(g), linear_to_srgb8(b))
+    }
+
+    /// The squared Euclidean distance to `other`, which is what the nearest
+    /// color searches compare.
+    #[inline]
+    pub(crate) fn dist_sq(&self, other: &OkLab) -> f32 {
+        let dl = self.l - other.l;
+        let da = self.a - other.a;
+        let db = self.b - other.b;
+        dl * dl + da * da + db * db
+    }
+
+    #[inline]
+    fn from_lms_cbrt(l: f32, m: f32, s: f32) -> Self {
         Self {
             l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
             a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
@@ -41,6 +96,71 @@ impl OkLab {
     }
 }
 
+#[inline]
+fn linear_to_lms(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    (
+        0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b,
+        0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b,
+        0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b,
+    )
+}
+
+#[inline]
+pub(crate) fn clamp01(f: f32) -> f32 {
+    // Written this way (rather than `f.clamp(0.0, 1.0)`) so that NaN becomes 0.
+    if f > 0.0 {
+        f.min(1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Decode an sRGB-encoded byte to linear light.
+#[inline]
+pub(crate) fn srgb8_to_linear(c: u8) -> f32 {
+    SRGB_TAB.0[c as usize]
+}
+
+/// Encode linear light back to an sRGB byte, rounding to the nearest entry.
+///
+/// This searches `SRGB_TAB` rather than evaluating the transfer function, so
+/// that it's exact with respect to [`srgb8_to_linear`], and so that we don't
+/// need `powf` (which isn't in `core`).
+#[inline]
+pub(crate) fn linear_to_srgb8(f: f32) -> u8 {
+    let tab: &[f32; 256] = &SRGB_TAB.0;
+    if f.is_nan() || f <= 0.0 {
+        return 0;
+    }
+    if f >= 1.0 {
+        return 255;
+    }
+    // `hi` is the first entry that's `>= f`, so `f` is between `hi - 1` and
+    // `hi`.
+    let hi = tab.partition_point(|&v| v < f);
+    debug_assert!(hi > 0 && hi < 256, "{hi} for {f}");
+    let lo = hi - 1;
+    if (f - tab[lo]) <= (tab[hi] - f) {
+        lo as u8
+    } else {
+        hi as u8
+    }
+}
+
+/// Composite `(r, g, b, alpha)` over an opaque background, in linear light.
+#[inline]
+pub(crate) fn blend_over(
+    (r, g, b, a): (u8, u8, u8, u8),
+    (br, bg, bb): (u8, u8, u8),
+) -> (u8, u8, u8) {
+    let a = a as f32 / 255.0;
+    let mix = |f: u8, b: u8| {
+        let (f, b) = (srgb8_to_linear(f), srgb8_to_linear(b));
+        linear_to_srgb8(b + (f - b) * a)
+    };
+    (mix(r, br), mix(g, bg), mix(b, bb))
+}
+
 // strictly speaking, our oklab_do_cbrt just cant be fed subnormals, but it's
 // fine to put the bound here for our inputs.
 const CBRT_MIN: f32 = 0.000001;
@@ -83,6 +203,22 @@ fn oklab_do_cbrt(f: f32) -> f32 {
     a as f32
 }
 
+/// Square root, since `f32::sqrt` isn't in `core`. Like `oklab_do_cbrt`, this
+/// only bothers with what we need (finite, non-negative inputs), and a few
+/// rounds of Newton's method in `f64` is plenty.
+#[inline]
+pub(crate) fn sqrt(f: f32) -> f32 {
+    if f <= 0.0 {
+        return 0.0;
+    }
+    let a = f32::from_bits((f.to_bits() >> 1) + 0x1fbd_1df5);
+    let (mut a, f) = (a as f64, f as f64);
+    for _ in 0..3 {
+        a = 0.5 * (a + f / a);
+    }
+    a as f32
+}
+
 #[rustfmt::skip]
 static SRGB_TAB: super::A64<[f32; 256]> = super::A64([
     0.0, 0.000303527, 0.000607054, 0.00091058103, 0.001214108, 0.001517635, 0.0018211621, 0.002124689,
@@ -333,4 +469,28 @@ mod test {
         /* rounding to 24 bits is perfect in round-to-nearest mode */
         t as f32
     }
+
+    #[test]
+    fn test_sqrt() {
+        for f in [0.0f32, 1e-6, 0.01, 0.25, 2.0, 12345.0] {
+            let want = std::primitive::f32::sqrt(f);
+            assert!((super::sqrt(f) - want).abs() <= f32::EPSILON * want, "{f}");
+        }
+    }
+
+    #[test]
+    fn test_blend_over() {
+        use super::blend_over;
+        let bg = (0x12, 0x80, 0xff);
+        assert_eq!(blend_over((1, 2, 3, 0xff), bg), (1, 2, 3));
+        assert_eq!(blend_over((1, 2, 3, 0), bg), bg);
+        // Half of white over black is much lighter than 0x80 in linear light.
+        assert_eq!(
+            blend_over((0xff, 0xff, 0xff, 0x80), (0, 0, 0)),
+            (0xbc, 0xbc, 0xbc)
+        );
+        for c in 0..=255 {
+            assert_eq!(blend_over((c, c, c, 0x80), (c, c, c)), (c, c, c));
+        }
+    }
 }
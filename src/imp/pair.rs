@@ -0,0 +1,145 @@
+//! Searching for a foreground/background pair together, so that text doesn't
+//! vanish when both sides get rounded to the same (or a too-similar) entry.
+use super::oklab::OkLab;
+use super::tab::LAB_PALETTE_ANSI256;
+
+/// How many of the nearest entries to one color are tried against every
+/// entry for the other. The best pair nearly always has at least one side
+/// among the first few, so this doesn't need to be large.
+const CANDIDATES: usize = 8;
+
+/// How much a change in the lightness difference counts for, compared to
+/// the error of either color. This is what keeps a subtle status bar subtle,
+/// rather than it becoming black on white.
+const DELTA_WEIGHT: f32 = 2.0;
+
+/// Differences in lightness smaller than this don't have a meaningful sign.
+const EPSILON: f32 = 1.0 / 512.0;
+
+pub(crate) fn nearest_pair_ansi256(fg: OkLab, bg: OkLab) -> (u8, u8) {
+    let table = &LAB_PALETTE_ANSI256[..];
+    let delta = fg.l - bg.l;
+    let cost = |i: usize, j: usize| -> f32 {
+        let (f, b) = (&table[i], &table[j]);
+        let d = f.l - b.l;
+        if i == j || (delta.abs() > EPSILON && d * delta <= 0.0) {
+            return f32::MAX;
+        }
+        let dd = d - delta;
+        f.dist_sq(&fg) + b.dist_sq(&bg) + DELTA_WEIGHT * dd * dd
+    };
+
+    let mut best = (f32::MAX, 0, 1);
+    let mut consider = |i: usize, j: usize| {
+        let c = cost(i, j);
+        if c < best.0 {
+            best = (c, i, j);
+        }
+    };
+    for i in nearest_n(fg, table) {
+        for j in 0..table.len() {
+            consider(i, j);
+        }
+    }
+    for j in nearest_n(bg, table) {
+        for i in 0..table.len() {
+            consider(i, j);
+        }
+    }
+    // Something is always found: of the (distinct) candidates for either
+    // side, at most one is the lightest or darkest entry, and the rest have
+    // something on the right side of them.
+    debug_assert!(best.0 < f32::MAX);
+    (best.1 as u8 + 16, best.2 as u8 + 16)
+}
+
+/// The indices of the `CANDIDATES` nearest entries to `v`, nearest first.
+fn nearest_n(v: OkLab, table: &[OkLab]) -> [usize; CANDIDATES] {
+    let mut out = [(f32::MAX, 0); CANDIDATES];
+    for (i, c) in table.iter().enumerate() {
+        let d = c.dist_sq(&v);
+        if d < out[CANDIDATES - 1].0 {
+            let at = out.iter().position(|&(o, _)| d < o).unwrap();
+            out.copy_within(at..CANDIDATES - 1, at + 1);
+            out[at] = (d, i);
+        }
+    }
+    out.map(|(_, i)| i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ansi256_to_rgb, nearest_ansi256};
+
+    fn pair(fg: (u8, u8, u8), bg: (u8, u8, u8)) -> (u8, u8) {
+        nearest_pair_ansi256(
+            OkLab::from_srgb8(fg.0, fg.1, fg.2),
+            OkLab::from_srgb8(bg.0, bg.1, bg.2),
+        )
+    }
+
+    fn lightness(i: u8) -> f32 {
+        let (r, g, b) = ansi256_to_rgb(i);
+        OkLab::from_srgb8(r, g, b).l
+    }
+
+    #[test]
+    fn test_pair() {
+        // Far enough apart that nothing needs to change.
+        let (fg, bg) = ((0xee, 0xee, 0xee), (0x12, 0x34, 0x56));
+        assert_eq!(
+            pair(fg, bg),
+            (
+                nearest_ansi256(0xee, 0xee, 0xee),
+                nearest_ansi256(0x12, 0x34, 0x56)
+            )
+        );
+
+        // A status bar, where the two would otherwise collide.
+        let (fg, bg) = ((0x5f, 0x87, 0xaf), (0x5a, 0x82, 0xaa));
+        assert_eq!(
+            nearest_ansi256(fg.0, fg.1, fg.2),
+            nearest_ansi256(bg.0, bg.1, bg.2)
+        );
+        let (f, b) = pair(fg, bg);
+        assert_ne!(f, b);
+        assert!(lightness(f) > lightness(b));
+
+        // Identical colors still get distinct indices.
+        let (f, b) = pair((0x80, 0x40, 0x20), (0x80, 0x40, 0x20));
+        assert_ne!(f, b);
+
+        // Text just barely darker than a white background stays darker.
+        let (f, b) = pair((0xfe, 0xfe, 0xfe), (0xff, 0xff, 0xff));
+        assert!(lightness(f) < lightness(b));
+
+        // At the ends of the palette, with nothing lighter than white (or
+        // darker than black) to move to, the other side moves instead.
+        let (f, b) = pair((0xff, 0xff, 0xff), (0xfe, 0xfe, 0xfe));
+        assert_eq!(f, 231);
+        assert!(lightness(b) < lightness(f));
+        let (f, b) = pair((0, 0, 0), (1, 1, 1));
+        assert_eq!(f, 16);
+        assert!(lightness(b) > lightness(f));
+    }
+
+    #[test]
+    fn test_pair_preserves_sign() {
+        // A crude sweep over near-collisions in both directions.
+        for base in (0..=255).step_by(17) {
+            for d in [-12i16, -4, 4, 12] {
+                let fg = (base as u8, (255 - base) as u8, 0x80);
+                let g = (fg.0 as i16 + d).clamp(0, 255) as u8;
+                let bg = (g, (fg.1 as i16 + d).clamp(0, 255) as u8, (0x80 + d) as u8);
+                let dl =
+                    OkLab::from_srgb8(fg.0, fg.1, fg.2).l - OkLab::from_srgb8(bg.0, bg.1, bg.2).l;
+                let (f, b) = pair(fg, bg);
+                assert_ne!(f, b, "{fg:?} {bg:?}");
+                if dl.abs() > EPSILON {
+                    assert!((lightness(f) - lightness(b)) * dl > 0.0, "{fg:?} {bg:?}");
+                }
+            }
+        }
+    }
+}
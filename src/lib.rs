@@ -20,6 +20,8 @@
 #![allow(dead_code)]
 #![cfg_attr(feature = "unstable-portable-simd", feature(portable_simd))]
 #![cfg_attr(benchmarking, feature(test))]
+#[cfg(feature = "alloc")]
+extern crate alloc;
 #[cfg(any(test, feature = "std"))]
 extern crate std;
 
@@ -28,6 +30,30 @@ mod benches;
 
 pub(crate) mod imp;
 
+pub mod adapt;
+pub mod color;
+pub mod colormap;
+pub mod contrast;
+pub mod cvd;
+#[cfg(feature = "std")]
+pub mod detect;
+pub mod distinct;
+pub mod gradient;
+#[cfg(feature = "std")]
+pub mod html;
+pub mod palette;
+#[cfg(feature = "std")]
+pub mod query;
+pub mod render;
+#[cfg(feature = "std")]
+pub mod rewrite;
+pub mod sgr;
+#[cfg(feature = "alloc")]
+pub mod sixel;
+pub mod stable;
+pub mod terminfo;
+pub mod theme;
+
 #[inline]
 pub fn nearest_ansi256(r: u8, g: u8, b: u8) -> u8 {
     imp::nearest_ansi256(r, g, b)
@@ -42,12 +68,202 @@ pub fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
     }
 }
 
+/// Find the nearest `(foreground, background)` pair of 256-color palette
+/// entries, searching both at once.
+///
+/// Calling [`nearest_ansi256`] on each separately can map two similar colors
+/// to the same entry, at which point the text disappears. This never returns
+/// the same index twice, keeps whichever side is lighter the lighter one, and
+/// tries to keep the difference in lightness about the same, while otherwise
+/// staying as close as possible to both colors.
+///
+/// ```
+/// let (fg, bg) = ((0x5f, 0x87, 0xaf), (0x5a, 0x82, 0xaa));
+/// assert_eq!(termpal::nearest_ansi256(0x5f, 0x87, 0xaf), 67);
+/// assert_eq!(termpal::nearest_ansi256(0x5a, 0x82, 0xaa), 67);
+///
+/// let (f, b) = termpal::nearest_pair_ansi256(fg, bg);
+/// assert_ne!(f, b);
+/// ```
+pub fn nearest_pair_ansi256(fg: (u8, u8, u8), bg: (u8, u8, u8)) -> (u8, u8) {
+    imp::pair::nearest_pair_ansi256(
+        imp::oklab::OkLab::from_srgb8(fg.0, fg.1, fg.2),
+        imp::oklab::OkLab::from_srgb8(bg.0, bg.1, bg.2),
+    )
+}
+
+/// Find the nearest 256-color palette entry to `fg` that has at least `min`
+/// contrast against `background`, which can be RGB or a palette index (see
+/// [`Background`]).
+///
+/// If nothing in the palette meets `min`, this returns whichever entry comes
+/// closest. Like [`nearest_ansi256`], the 16 named colors are never returned,
+/// but they can be used as the background, in which case their xterm defaults
+/// are assumed.
+///
+/// ```
+/// use termpal::contrast::MinContrast;
+///
+/// // Dark blue text on black is unreadable, so this picks a lighter blue.
+/// let i = termpal::nearest_ansi256_with_contrast((0x20, 0x30, 0x90), 16, MinContrast::Wcag(4.5));
+/// assert_eq!(termpal::nearest_ansi256(0x20, 0x30, 0x90), 19);
+/// assert_eq!(termpal::ansi256_to_rgb(i), (0x5f, 0x5f, 0xff));
+/// ```
+pub fn nearest_ansi256_with_contrast(
+    fg: (u8, u8, u8),
+    background: impl Into<Background>,
+    min: contrast::MinContrast,
+) -> u8 {
+    contrast::nearest_ansi256_with_contrast(fg, background.into().to_rgb(), min)
+}
+
+/// Assign each of `colors` a 256-color palette entry, keeping distinct colors
+/// on distinct entries.
+///
+/// A theme's colors often collapse onto a handful of entries when converted
+/// one at a time with [`nearest_ansi256`], so that e.g. "error" and "warning"
+/// end up the same. This instead finds the assignment with the least total
+/// (squared Oklab) error where no two different colors share an entry.
+/// Repeated colors get the same entry.
+///
+/// There are only 240 entries to go around (the 16 named colors are never
+/// used), so past the 240th distinct color, the rest just get their nearest
+/// entry.
+///
+/// ```
+/// let theme = [(0xff, 0x5f, 0x5f), (0xf0, 0x60, 0x60)];
+/// assert_eq!(termpal::nearest_ansi256(0xf0, 0x60, 0x60), 203);
+/// let indices = termpal::assign_distinct_ansi256(&theme);
+/// assert_eq!(indices[0], 203);
+/// assert_ne!(indices[1], 203);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn assign_distinct_ansi256(colors: &[(u8, u8, u8)]) -> alloc::vec::Vec<u8> {
+    imp::assign::assign_distinct_ansi256(colors)
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// The same as [`assign_distinct_ansi256`], but also returns the cost of each
+/// assignment: the squared Oklab distance from the color to its entry (the
+/// same thing the assignment minimizes the total of).
+///
+/// This is handy for warning about theme colors that came out too far from
+/// what was asked for.
+#[cfg(feature = "alloc")]
+pub fn assign_distinct_ansi256_with_costs(colors: &[(u8, u8, u8)]) -> alloc::vec::Vec<(u8, f32)> {
+    imp::assign::assign_distinct_ansi256(colors)
+}
+
+/// An opaque background color, for [`nearest_ansi256_over`],
+/// [`nearest_ansi256_with_contrast`] and the like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Background {
+    Rgb(u8, u8, u8),
+    /// A palette index, resolved with [`ansi256_to_rgb`] (so the named colors
+    /// are assumed to have their xterm defaults).
+    Indexed(u8),
+}
+
+impl Background {
+    #[inline]
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Background::Rgb(r, g, b) => (r, g, b),
+            Background::Indexed(i) => ansi256_to_rgb(i),
+        }
+    }
+}
+
+impl From<(u8, u8, u8)> for Background {
+    #[inline]
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Background::Rgb(r, g, b)
+    }
+}
+
+impl From<u8> for Background {
+    #[inline]
+    fn from(index: u8) -> Self {
+        Background::Indexed(index)
+    }
+}
+
+/// Composite `(r, g, b, alpha)` over `background`, returning the opaque
+/// result.
+///
+/// The blend happens in linear light, rather than on the sRGB-encoded values,
+/// which is what gets the brightness of a 50% overlay right.
+///
+/// ```
+/// // Half of white over black is a good deal lighter than `0x80`.
+/// assert_eq!(termpal::composite_over((0xff, 0xff, 0xff, 0x80), (0, 0, 0)), (0xbc, 0xbc, 0xbc));
+/// ```
+#[inline]
+pub fn composite_over(rgba: (u8, u8, u8, u8), background: impl Into<Background>) -> (u8, u8, u8) {
+    imp::oklab::blend_over(rgba, background.into().to_rgb())
+}
+
+/// The same as [`nearest_ansi256`], for a translucent color drawn over
+/// `background`, which may be either an RGB color or a palette index.
+///
+/// ```
+/// // A 25% red overlay on a cell with a dark grey (236) background.
+/// let index = termpal::nearest_ansi256_over((0xff, 0, 0, 0x40), 236);
+/// let (r, g, b) = termpal::ansi256_to_rgb(index);
+/// assert!(r > g && g == b);
+/// ```
+#[inline]
+pub fn nearest_ansi256_over(rgba: (u8, u8, u8, u8), background: impl Into<Background>) -> u8 {
+    let (r, g, b) = composite_over(rgba, background);
+    nearest_ansi256(r, g, b)
+}
+
+/// Pick a color for `key` (a nickname, hostname, log source...) by hashing
+/// it onto a fixed set of colorful 256-color palette entries, skipping any
+/// that aren't readable against `background`.
+///
+/// The same key and background always give the same index, on every
+/// platform and in every version of this crate. A key also keeps its color
+/// across backgrounds, as long as that color is readable on both.
+///
+/// ```
+/// let on_dark = termpal::hash_to_ansi256(b"alice", (0x1e, 0x1e, 0x2e));
+/// let on_light = termpal::hash_to_ansi256(b"alice", 231);
+/// println!("\x1b[38;5;{on_dark}malice\x1b[0m");
+/// # let _ = on_light;
+/// ```
+#[inline]
+pub fn hash_to_ansi256(key: &[u8], background: impl Into<Background>) -> u8 {
+    imp::hash::hash_to_ansi256(key, background.into().to_rgb())
+}
+
 #[inline]
 #[cfg(feature = "88color")]
 pub fn nearest_ansi88(r: u8, g: u8, b: u8) -> u8 {
     imp::nearest_ansi88(r, g, b)
 }
 
+/// The set of colors a terminal can display, which determines the kind of SGR
+/// color sequences that should be emitted for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorDepth {
+    /// 24-bit "true color", using `38;2;r;g;b`-style sequences.
+    TrueColor,
+    /// The `xterm-256color` palette, using `38;5;n`-style sequences, where `n`
+    /// comes from [`nearest_ansi256`].
+    Ansi256,
+    /// The `xterm-88color` palette, using `38;5;n`-style sequences, where `n`
+    /// comes from [`nearest_ansi88`].
+    #[cfg(feature = "88color")]
+    Ansi88,
+    /// Only the 16 named colors, using `30`-`37` and `90`-`97` (and the
+    /// equivalent background sequences). These are usually customized by the
+    /// user, so results are approximate.
+    Ansi16,
+}
+
 /// Conversion methods equivalent to the top-level API that bypass the cache.
 ///
 /// By default, functions like [`nearest_ansi256`] will check the a cache before
@@ -0,0 +1,538 @@
+//! Searching arbitrary palettes, rather than the fixed xterm tables.
+//!
+//! This is for cases where we control (or know) the palette, such as Sixel
+//! color registers, or a terminal whose palette we've queried. A [`Palette`]
+//! holds up to 256 colors, along with their precomputed Oklab values, so that
+//! [`Palette::nearest`] uses the same distance metric as
+//! [`nearest_ansi256`](crate::nearest_ansi256).
+//!
+//! Unlike the xterm tables, the entries here are not assumed to be
+//! user-customized, so every entry (including `0..16`) may be returned.
+use crate::imp::fallback::nearest_impl;
+use crate::imp::oklab::{oklab, OkLab};
+
+/// The maximum number of colors a [`Palette`] can hold.
+pub const MAX_COLORS: usize = 256;
+
+/// A list of up to 256 sRGB colors, which can be searched for the nearest
+/// entry to a given color.
+///
+/// This doesn't allocate, but is fairly large (~4kB), so you may want to box it
+/// if you need to move it around a lot.
+#[derive(Clone)]
+pub struct Palette {
+    len: usize,
+    rgb: [(u8, u8, u8); MAX_COLORS],
+    lab: [OkLab; MAX_COLORS],
+}
+
+impl Palette {
+    /// An empty palette.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            len: 0,
+            rgb: [(0, 0, 0); MAX_COLORS],
+            lab: [oklab(0.0, 0.0, 0.0); MAX_COLORS],
+        }
+    }
+
+    /// A palette containing `colors`, in order.
+    ///
+    /// # Panics
+    ///
+    /// If there are more than [`MAX_COLORS`] colors.
+    #[track_caller]
+    pub fn from_rgb(colors: &[(u8, u8, u8)]) -> Self {
+        assert!(
+            colors.len() <= MAX_COLORS,
+            "too many colors for a palette: {}",
+            colors.len(),
+        );
+        let mut p = Self::new();
+        for &c in colors {
+            p.push(c);
+        }
+        p
+    }
+
+    /// Append `color`, returning its index, or `None` if the palette is full.
+    #[inline]
+    pub fn push(&mut self, color: (u8, u8, u8)) -> Option<u8> {
+        if self.len == MAX_COLORS {
+            return None;
+        }
+        let i = self.len;
+        self.set_at(i, color);
+        self.len += 1;
+        Some(i as u8)
+    }
+
+    /// Replace the color at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    #[inline]
+    #[track_caller]
+    pub fn set(&mut self, index: u8, color: (u8, u8, u8)) {
+        assert!((index as usize) < self.len, "index out of bounds: {index}");
+        self.set_at(index as usize, color);
+    }
+
+    #[inline]
+    fn set_at(&mut self, i: usize, (r, g, b): (u8, u8, u8)) {
+        self.rgb[i] = (r, g, b);
+        self.lab[i] = OkLab::from_srgb8(r, g, b);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn get(&self, index: u8) -> Option<(u8, u8, u8)> {
+        self.colors().get(index as usize).copied()
+    }
+
+    #[inline]
+    pub fn colors(&self) -> &[(u8, u8, u8)] {
+        &self.rgb[..self.len]
+    }
+
+    /// Returns the index of the entry nearest to `(r, g, b)`.
+    ///
+    /// This performs a full (uncached, non-SIMD) search, so is a good deal
+    /// slower than [`nearest_ansi256`](crate::nearest_ansi256). An empty
+    /// palette always returns 0.
+    #[inline]
+    pub fn nearest(&self, r: u8, g: u8, b: u8) -> u8 {
+        self.nearest_lab(OkLab::from_srgb8(r, g, b))
+    }
+
+    #[inline]
+    pub(crate) fn nearest_lab(&self, lab: OkLab) -> u8 {
+        if self.len == 0 {
+            return 0;
+        }
+        nearest_impl(lab, self.labs()) as u8
+    }
+
+    #[inline]
+    pub(crate) fn labs(&self) -> &[OkLab] {
+        &self.lab[..self.len]
+    }
+}
+
+impl Default for Palette {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for Palette {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.colors()).finish()
+    }
+}
+
+impl PartialEq for Palette {
+    #[inline]
+    fn eq(&self, o: &Self) -> bool {
+        self.colors() == o.colors()
+    }
+}
+
+impl Eq for Palette {}
+
+#[cfg(feature = "alloc")]
+impl Palette {
+    /// Build a palette of at most `max_colors` (clamped to `1..=256`) colors
+    /// that represent `colors` well, using median cut in Oklab.
+    ///
+    /// Repeated colors are weighted by how often they occur, so this is
+    /// intended to be fed every pixel of an image (see
+    /// [`Image::pixels`](crate::render::Image::pixels)).
+    pub fn median_cut(colors: impl IntoIterator<Item = (u8, u8, u8)>, max_colors: usize) -> Self {
+        let max_colors = max_colors.clamp(1, MAX_COLORS);
+        let mut entries = histogram(colors);
+        let mut p = Self::new();
+        for (lab, _) in median_cut(&mut entries, max_colors) {
+            p.push(lab.to_srgb8());
+        }
+        p
+    }
+}
+
+/// Builds a palette that represents a set of colors (such as the pixels of an
+/// image) well.
+///
+/// This runs median cut in Oklab to get an initial palette, and then refines
+/// it with (weighted) k-means, which usually reduces the error quite a bit.
+/// Everything is deterministic: the same input, options and seed always
+/// produce the same palette, on every platform.
+///
+/// ```
+/// use termpal::palette::Builder;
+///
+/// let pixels = [(250, 10, 10), (240, 20, 0), (10, 10, 250), (0, 20, 240)];
+/// let palette = Builder::new(2).build(pixels);
+/// assert_eq!(palette.len(), 2);
+/// assert_ne!(palette.nearest(255, 0, 0), palette.nearest(0, 0, 255));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug)]
+pub struct Builder {
+    colors: usize,
+    iterations: usize,
+    max_samples: usize,
+    seed: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl Builder {
+    /// A builder for palettes of at most `colors` entries (clamped to
+    /// `1..=256`).
+    #[inline]
+    pub const fn new(colors: usize) -> Self {
+        Self {
+            colors,
+            iterations: 16,
+            max_samples: 1 << 16,
+            seed: 0,
+        }
+    }
+
+    /// The maximum number of k-means iterations to run after median cut. The
+    /// refinement stops early if it converges. Zero gives plain median cut.
+    #[inline]
+    pub const fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// If the input has more than this many distinct colors, k-means only
+    /// considers a (seeded) random subset of this size, since each iteration
+    /// is `O(distinct colors × palette size)`.
+    #[inline]
+    pub const fn max_samples(mut self, max_samples: usize) -> Self {
+        self.max_samples = max_samples;
+        self
+    }
+
+    /// Seed for the random choices made while building. Defaults to 0.
+    #[inline]
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Build a palette for `colors`. Repeated colors are weighted by how
+    /// often they occur.
+    pub fn build(&self, colors: impl IntoIterator<Item = (u8, u8, u8)>) -> Palette {
+        let n = self.colors.clamp(1, MAX_COLORS);
+        let mut entries = histogram(colors);
+        // Median cut uses every entry, since it's cheap, and we'd rather not
+        // lose rare (but distinct) colors before we've even started.
+        let mut centroids: alloc::vec::Vec<OkLab> = median_cut(&mut entries, n)
+            .into_iter()
+            .map(|(lab, _)| lab)
+            .collect();
+        if self.iterations != 0 {
+            let mut rng = Rng(self.seed);
+            if entries.len() > self.max_samples.max(1) {
+                // Partial Fisher-Yates shuffle, keeping the first
+                // `max_samples`.
+                for i in 0..self.max_samples.max(1) {
+                    let j = i + rng.below(entries.len() - i);
+                    entries.swap(i, j);
+                }
+                entries.truncate(self.max_samples.max(1));
+            }
+            kmeans(&entries, &mut centroids, self.iterations);
+        }
+        let mut p = Palette::new();
+        for lab in centroids {
+            p.push(lab.to_srgb8());
+        }
+        p
+    }
+}
+
+/// Refine `centroids` using weighted Lloyd's algorithm.
+#[cfg(feature = "alloc")]
+fn kmeans(entries: &[Weighted], centroids: &mut [OkLab], iterations: usize) {
+    use alloc::vec;
+    let k = centroids.len();
+    let mut sums = vec![[0.0f32; 4]; k];
+    for _ in 0..iterations {
+        sums.iter_mut().for_each(|s| *s = [0.0; 4]);
+        // Entry with the most error, used to reseed any empty clusters.
+        let mut worst = (0.0f32, None);
+        for e in entries {
+            let c = nearest_impl(e.lab, centroids);
+            let s = &mut sums[c];
+            s[0] += e.lab.l * e.weight;
+            s[1] += e.lab.a * e.weight;
+            s[2] += e.lab.b * e.weight;
+            s[3] += e.weight;
+            let err = e.lab.dist_sq(&centroids[c]) * e.weight;
+            if err > worst.0 {
+                worst = (err, Some(e.lab));
+            }
+        }
+        let mut moved = false;
+        for (c, s) in centroids.iter_mut().zip(&sums) {
+            let next = if s[3] > 0.0 {
+                oklab(s[0] / s[3], s[1] / s[3], s[2] / s[3])
+            } else if let Some(lab) = worst.1.take() {
+                lab
+            } else {
+                continue;
+            };
+            // About a fifth of the smallest difference we could display.
+            moved |= c.dist_sq(&next) > 1e-8;
+            *c = next;
+        }
+        if !moved {
+            break;
+        }
+    }
+}
+
+/// Small deterministic PRNG (SplitMix64), so palettes are reproducible
+/// without depending on `rand`.
+#[cfg(feature = "alloc")]
+struct Rng(u64);
+
+#[cfg(feature = "alloc")]
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n` (with negligible bias for the `n` we use).
+    fn below(&mut self, n: usize) -> usize {
+        (((self.next() >> 32) * n as u64) >> 32) as usize
+    }
+}
+
+/// A distinct color, along with how many times it occurred.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Weighted {
+    pub(crate) lab: OkLab,
+    pub(crate) weight: f32,
+}
+
+/// Deduplicate `colors`, converting each one to Oklab.
+#[cfg(feature = "alloc")]
+pub(crate) fn histogram(
+    colors: impl IntoIterator<Item = (u8, u8, u8)>,
+) -> alloc::vec::Vec<Weighted> {
+    use alloc::vec::Vec;
+    let mut packed: Vec<u32> = colors
+        .into_iter()
+        .map(|(r, g, b)| u32::from_be_bytes([0, r, g, b]))
+        .collect();
+    packed.sort_unstable();
+    let mut out: Vec<Weighted> = Vec::new();
+    let mut prev = None;
+    for c in packed {
+        if prev == Some(c) {
+            if let Some(last) = out.last_mut() {
+                last.weight += 1.0;
+            }
+            continue;
+        }
+        prev = Some(c);
+        let [_, r, g, b] = c.to_be_bytes();
+        out.push(Weighted {
+            lab: OkLab::from_srgb8(r, g, b),
+            weight: 1.0,
+        });
+    }
+    out
+}
+
+/// Splits `entries` into at most `n` boxes, returning the weighted mean and
+/// total weight of each one.
+#[cfg(feature = "alloc")]
+pub(crate) fn median_cut(entries: &mut [Weighted], n: usize) -> alloc::vec::Vec<(OkLab, f32)> {
+    use alloc::vec::Vec;
+    if entries.is_empty() {
+        return Vec::new();
+    }
+    let mut boxes = alloc::vec![BoxStats::new(entries, 0..entries.len())];
+    while boxes.len() < n {
+        // Split the box with the most error, if any of them can be split.
+        let Some((bi, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.range.len() > 1 && b.sse > 0.0)
+            .max_by(|(_, a), (_, b)| a.sse.total_cmp(&b.sse))
+        else {
+            break;
+        };
+        let b = boxes.swap_remove(bi);
+        let slice = &mut entries[b.range.clone()];
+        slice.sort_unstable_by(|x, y| axis(&x.lab, b.axis).total_cmp(&axis(&y.lab, b.axis)));
+        // Split at the weighted median, keeping both halves nonempty.
+        let half = slice.iter().map(|e| e.weight).sum::<f32>() / 2.0;
+        let mut acc = 0.0;
+        let mut k = 1;
+        for (i, e) in slice.iter().enumerate() {
+            acc += e.weight;
+            if acc >= half {
+                k = i + 1;
+                break;
+            }
+        }
+        let k = k.clamp(1, slice.len() - 1);
+        let mid = b.range.start + k;
+        boxes.push(BoxStats::new(entries, b.range.start..mid));
+        boxes.push(BoxStats::new(entries, mid..b.range.end));
+    }
+    boxes.iter().map(|b| (b.mean, b.weight)).collect()
+}
+
+#[cfg(feature = "alloc")]
+#[inline]
+fn axis(lab: &OkLab, axis: usize) -> f32 {
+    match axis {
+        0 => lab.l,
+        1 => lab.a,
+        _ => lab.b,
+    }
+}
+
+#[cfg(feature = "alloc")]
+struct BoxStats {
+    range: core::ops::Range<usize>,
+    mean: OkLab,
+    weight: f32,
+    /// Total (weighted) squared error vs `mean`.
+    sse: f32,
+    /// The axis with the most variance.
+    axis: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl BoxStats {
+    fn new(entries: &[Weighted], range: core::ops::Range<usize>) -> Self {
+        let slice = &entries[range.clone()];
+        let mut weight = 0.0;
+        let mut sum = [0.0f32; 3];
+        for e in slice {
+            weight += e.weight;
+            for (c, s) in sum.iter_mut().enumerate() {
+                *s += axis(&e.lab, c) * e.weight;
+            }
+        }
+        let mean = sum.map(|s| s / weight);
+        let mut var = [0.0f32; 3];
+        for e in slice {
+            for (c, v) in var.iter_mut().enumerate() {
+                let d = axis(&e.lab, c) - mean[c];
+                *v += d * d * e.weight;
+            }
+        }
+        let best = if var[0] >= var[1] && var[0] >= var[2] {
+            0
+        } else if var[1] >= var[2] {
+            1
+        } else {
+            2
+        };
+        Self {
+            range,
+            mean: oklab(mean[0], mean[1], mean[2]),
+            weight,
+            sse: var[0] + var[1] + var[2],
+            axis: best,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nearest() {
+        let p = Palette::from_rgb(&[(0, 0, 0), (255, 0, 0), (0, 0, 255), (255, 255, 255)]);
+        assert_eq!(p.len(), 4);
+        assert_eq!(p.nearest(250, 10, 10), 1);
+        assert_eq!(p.nearest(10, 10, 200), 2);
+        assert_eq!(p.nearest(20, 20, 20), 0);
+        assert_eq!(p.nearest(230, 230, 230), 3);
+        assert_eq!(Palette::new().nearest(1, 2, 3), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_median_cut() {
+        use std::vec::Vec;
+        let mut colors = Vec::new();
+        for _ in 0..100 {
+            colors.push((200, 20, 20));
+            colors.push((20, 200, 20));
+            colors.push((20, 20, 200));
+        }
+        let p = Palette::median_cut(colors.iter().copied(), 3);
+        assert_eq!(p.len(), 3);
+        for &c in &[(200, 20, 20), (20, 200, 20), (20, 20, 200)] {
+            let got = p.get(p.nearest(c.0, c.1, c.2)).unwrap();
+            assert!(
+                got.0.abs_diff(c.0) <= 3 && got.1.abs_diff(c.1) <= 3 && got.2.abs_diff(c.2) <= 3,
+                "{c:?} => {got:?} in {p:?}",
+            );
+        }
+        // Asking for more colors than there are just gives the distinct ones.
+        colors.push((210, 25, 15));
+        assert_eq!(Palette::median_cut(colors.iter().copied(), 256).len(), 4);
+        assert!(Palette::median_cut(core::iter::empty(), 16).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_builder() {
+        use std::vec::Vec;
+        // A smooth gradient, which median cut alone handles poorly.
+        let colors: Vec<(u8, u8, u8)> = (0..=255u8)
+            .flat_map(|i| (0..=255u8).step_by(17).map(move |j| (i, j, 255 - i)))
+            .collect();
+        let total_err = |p: &Palette| -> f32 {
+            colors
+                .iter()
+                .map(|&(r, g, b)| {
+                    let lab = OkLab::from_srgb8(r, g, b);
+                    lab.dist_sq(&p.labs()[p.nearest_lab(lab) as usize])
+                })
+                .sum()
+        };
+        let mc = Builder::new(16).iterations(0).build(colors.iter().copied());
+        assert_eq!(mc, Palette::median_cut(colors.iter().copied(), 16));
+        let km = Builder::new(16).build(colors.iter().copied());
+        assert_eq!(km.len(), 16);
+        assert!(total_err(&km) < total_err(&mc), "{km:?} vs {mc:?}");
+
+        // Deterministic, including when subsampling.
+        let b = Builder::new(16).max_samples(100).seed(1234);
+        assert_eq!(
+            b.build(colors.iter().copied()),
+            b.build(colors.iter().copied())
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Asking the terminal what its colors actually are.
+//!
+//! Most terminals answer OSC 4 (palette entry), OSC 10 (default foreground)
+//! and OSC 11 (default background) queries with an X11-style color spec, e.g.
+//! `\x1b]4;1;rgb:cdcd/0000/0000\x07`. Doing this lets you search the user's
+//! real palette (using [`Colors::palette`]), which matters most for the 16
+//! named colors, as nearly everyone's are customized.
+//!
+//! Some terminals don't answer these at all, so the queries are followed by a
+//! primary device attributes request (`\x1b[c`), which everything answers.
+//! Once that reply arrives there's no point waiting any longer, so in practice
+//! the timeout is only hit when the other end isn't a terminal.
+//!
+//! The terminal needs to be in raw mode (no echo, and not line-buffered) while
+//! the query runs, or the replies will show up on screen and won't be readable
+//! until the user presses enter. That's left up to you, as it needs
+//! platform-specific code most terminal applications already have. Any input
+//! that arrives during the query (such as keypresses) is discarded.
+use crate::palette::Palette;
+use core::fmt::Write as _;
+use core::time::Duration;
+use std::io;
+use std::string::String;
+use std::time::Instant;
+use std::vec::Vec;
+
+type Rgb = (u8, u8, u8);
+
+/// Something that queries can be written to, and replies read from.
+///
+/// On unix, this is implemented for [`File`](std::fs::File), so `/dev/tty`
+/// can be used directly. It's also easy to implement for a stand-in that plays
+/// back canned replies, for testing.
+pub trait Tty {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    /// Read some bytes into `buf`, waiting at most `timeout` for them to
+    /// arrive. Returns `Ok(0)` if nothing arrived in time.
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+impl<T: Tty + ?Sized> Tty for &mut T {
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        (**self).write_all(buf)
+    }
+
+    #[inline]
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        (**self).read_timeout(buf, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Tty for std::fs::File {
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::Write::write_all(self, buf)?;
+        io::Write::flush(self)
+    }
+
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        use std::os::unix::io::AsRawFd;
+        if !sys::poll_readable(self.as_raw_fd(), timeout)? {
+            return Ok(0);
+        }
+        io::Read::read(self, buf)
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use core::ffi::{c_int, c_short};
+    use core::time::Duration;
+    use std::io;
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    // The same value everywhere we care about.
+    const POLLIN: c_short = 1;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    type NFds = core::ffi::c_ulong;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    type NFds = core::ffi::c_uint;
+
+    extern "C" {
+        fn poll(fds: *mut PollFd, nfds: NFds, timeout: c_int) -> c_int;
+    }
+
+    /// Wait for `fd` to become readable, returning false on timeout.
+    pub(super) fn poll_readable(fd: c_int, timeout: Duration) -> io::Result<bool> {
+        // Round up, so that we don't spin with a zero timeout.
+        let ms = timeout
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(c_int::MAX as u128) as c_int;
+        let mut pfd = PollFd {
+            fd,
+            events: POLLIN,
+            revents: 0,
+        };
+        loop {
+            // SAFETY: `pfd` is a valid `pollfd`, and we pass a count of 1.
+            let r = unsafe { poll(&mut pfd, 1, ms) };
+            if r >= 0 {
+                return Ok(r > 0);
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// The colors reported by the terminal. Anything it didn't answer for is
+/// `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Colors {
+    pub indexed: [Option<Rgb>; 256],
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+}
+
+impl Default for Colors {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            indexed: [None; 256],
+            foreground: None,
+            background: None,
+        }
+    }
+}
+
+impl Colors {
+    /// A palette of the first `len` entries, with the ones the terminal didn't
+    /// report filled in by [`ansi256_to_rgb`](crate::ansi256_to_rgb).
+    ///
+    /// # Panics
+    ///
+    /// If `len` is more than 256.
+    #[track_caller]
+    pub fn palette(&self, len: usize) -> Palette {
+        assert!(len <= 256, "palette length {len} is more than 256");
+        let mut p = Palette::new();
+        for (i, c) in self.indexed[..len].iter().enumerate() {
+            p.push(c.unwrap_or_else(|| crate::ansi256_to_rgb(i as u8)));
+        }
+        p
+    }
+
+    /// Handle the complete replies at the start of `buf`, returning the number
+    /// of bytes used, and whether the device attributes reply was seen.
+    fn parse_replies(&mut self, buf: &[u8]) -> (usize, bool) {
+        let mut pos = 0;
+        while let Some(i) = buf[pos..].iter().position(|&b| b == 0x1b) {
+            let start = pos + i;
+            let rest = &buf[start..];
+            match rest.get(1) {
+                Some(b']') => {
+                    let Some((body, len)) = osc_body(&rest[2..]) else {
+                        return (start, false);
+                    };
+                    self.osc_reply(body);
+                    pos = start + 2 + len;
+                }
+                Some(b'[') => {
+                    let Some(end) = rest[2..].iter().position(|b| (0x40..=0x7e).contains(b)) else {
+                        return (start, false);
+                    };
+                    pos = start + 2 + end + 1;
+                    if rest[2] == b'?' && rest[2 + end] == b'c' {
+                        return (pos, true);
+                    }
+                }
+                Some(_) => pos = start + 1,
+                None => return (start, false),
+            }
+        }
+        (buf.len(), false)
+    }
+
+    fn osc_reply(&mut self, body: &[u8]) {
+        let mut fields = body.splitn(3, |&b| b == b';');
+        match fields.next() {
+            Some(b"4") => {
+                let index = fields.next().and_then(|s| core::str::from_utf8(s).ok());
+                if let (Some(Ok(i)), Some(spec)) = (index.map(str::parse::<u8>), fields.next()) {
+                    if let Some(c) = parse_color(spec) {
+                        self.indexed[i as usize] = Some(c);
+                    }
+                }
+            }
+            Some(b"10") => self.foreground = fields.next().and_then(parse_color),
+            Some(b"11") => self.background = fields.next().and_then(parse_color),
+            _ => {}
+        }
+    }
+}
+
+/// Find the end of an OSC string (after the `ESC ]`), which is either `BEL`
+/// or `ESC \`. Returns the body, and the length including the terminator.
+fn osc_body(s: &[u8]) -> Option<(&[u8], usize)> {
+    let end = s.iter().position(|&b| b == 0x07 || b == 0x1b)?;
+    if s[end] == 0x07 {
+        return Some((&s[..end], end + 1));
+    }
+    match s.get(end + 1)? {
+        b'\\' => Some((&s[..end], end + 2)),
+        // Some other sequence cut this one off.
+        _ => Some((&s[..end], end)),
+    }
+}
+
+/// Parse an X11 color spec of the form `rgb:r/g/b`, where each component is 1
+/// to 4 hex digits, as terminals use in their replies.
+///
+/// ```
+/// use termpal::query::parse_color;
+///
+/// assert_eq!(parse_color(b"rgb:ffff/8080/0000"), Some((0xff, 0x80, 0x00)));
+/// assert_eq!(parse_color(b"rgb:f/80/000"), Some((0xff, 0x80, 0x00)));
+/// assert_eq!(parse_color(b"#ff8000"), None);
+/// ```
+pub fn parse_color(spec: &[u8]) -> Option<Rgb> {
+    let rest = spec.strip_prefix(b"rgb:")?;
+    let mut parts = rest.split(|&b| b == b'/');
+    let mut component = || -> Option<u8> {
+        let digits = parts.next()?;
+        // `from_str_radix` would also take a sign.
+        if digits.is_empty() || digits.len() > 4 || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let v = u32::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+        // Scale from however many digits there were to 8 bits.
+        let max = (1u32 << (4 * digits.len())) - 1;
+        Some(((v * 255 + max / 2) / max) as u8)
+    };
+    let rgb = (component()?, component()?, component()?);
+    parts.next().is_none().then_some(rgb)
+}
+
+/// A query for the terminal's colors.
+///
+/// ```no_run
+/// # #[cfg(unix)] {
+/// use std::time::Duration;
+/// use termpal::query::Query;
+///
+/// // (Put the terminal in raw mode first.)
+/// let mut tty = std::fs::File::options()
+///     .read(true)
+///     .write(true)
+///     .open("/dev/tty")
+///     .unwrap();
+/// let colors = Query::new()
+///     .indices(16)
+///     .timeout(Duration::from_millis(200))
+///     .run(&mut tty)
+///     .unwrap();
+/// let palette = colors.palette(16);
+/// let nearest_to_orange = palette.nearest(0xff, 0x80, 0x00);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Query {
+    indices: usize,
+    foreground: bool,
+    background: bool,
+    timeout: Duration,
+}
+
+impl Default for Query {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Query {
+    /// Query all 256 palette entries, and the default foreground and
+    /// background, with a timeout of 100ms.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            indices: 256,
+            foreground: true,
+            background: true,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Only query palette entries `0..n` (clamped to 256). Usually only the
+    /// first 16 are customized.
+    #[inline]
+    pub const fn indices(mut self, n: usize) -> Self {
+        self.indices = if n > 256 { 256 } else { n };
+        self
+    }
+
+    #[inline]
+    pub const fn foreground(mut self, on: bool) -> Self {
+        self.foreground = on;
+        self
+    }
+
+    #[inline]
+    pub const fn background(mut self, on: bool) -> Self {
+        self.background = on;
+        self
+    }
+
+    /// How long to wait for all the replies, in total.
+    #[inline]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Write the queries to `tty`, and collect the replies until the terminal
+    /// has answered everything, or the timeout expires.
+    pub fn run<T: Tty + ?Sized>(&self, tty: &mut T) -> io::Result<Colors> {
+        let mut req = String::new();
+        for i in 0..self.indices {
+            let _ = write!(req, "\x1b]4;{i};?\x07");
+        }
+        if self.foreground {
+            req.push_str("\x1b]10;?\x07");
+        }
+        if self.background {
+            req.push_str("\x1b]11;?\x07");
+        }
+        req.push_str("\x1b[c");
+        tty.write_all(req.as_bytes())?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut colors = Colors::default();
+        let mut pending = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            let n = tty.read_timeout(&mut buf, left)?;
+            if n == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buf[..n]);
+            let (used, done) = colors.parse_replies(&pending);
+            if done {
+                break;
+            }
+            pending.drain(..used);
+        }
+        Ok(colors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Plays back canned replies, one per read.
+    struct Canned {
+        written: Vec<u8>,
+        replies: VecDeque<&'static [u8]>,
+    }
+
+    impl Canned {
+        fn new(replies: &[&'static [u8]]) -> Self {
+            Self {
+                written: Vec::new(),
+                replies: replies.iter().copied().collect(),
+            }
+        }
+    }
+
+    impl Tty for Canned {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.written.extend_from_slice(buf);
+            Ok(())
+        }
+
+        fn read_timeout(&mut self, buf: &mut [u8], _: Duration) -> io::Result<usize> {
+            let Some(r) = self.replies.pop_front() else {
+                return Ok(0);
+            };
+            buf[..r.len()].copy_from_slice(r);
+            Ok(r.len())
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let mut tty = Canned::new(&[
+            b"\x1b]4;0;rgb:0000/0000/0000\x07\x1b]4;1;rgb:cd",
+            b"cd/0000/0000\x1b\\x\x1b]4;2;rgb:0/ff/000\x07",
+            b"\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:1e1e/1e1e/2e2e\x1b",
+            b"\\\x1b[?62;",
+            b"22c",
+            b"\x1b]4;3;rgb:ff/ff/ff\x07",
+        ]);
+        let colors = Query::new().indices(4).run(&mut tty).unwrap();
+        assert_eq!(
+            tty.written,
+            b"\x1b]4;0;?\x07\x1b]4;1;?\x07\x1b]4;2;?\x07\x1b]4;3;?\x07\x1b]10;?\x07\x1b]11;?\x07\x1b[c"
+        );
+        // Stopped after the DA1 reply.
+        assert_eq!(tty.replies.len(), 1);
+        assert_eq!(colors.indexed[0], Some((0, 0, 0)));
+        assert_eq!(colors.indexed[1], Some((0xcd, 0, 0)));
+        assert_eq!(colors.indexed[2], Some((0, 0xff, 0)));
+        assert_eq!(colors.indexed[3], None);
+        assert_eq!(colors.foreground, Some((0xff, 0xff, 0xff)));
+        assert_eq!(colors.background, Some((0x1e, 0x1e, 0x2e)));
+
+        let p = colors.palette(4);
+        assert_eq!(p.len(), 4);
+        assert_eq!(p.get(1), Some((0xcd, 0, 0)));
+        assert_eq!(p.get(3), Some(crate::ansi256_to_rgb(3)));
+    }
+
+    #[test]
+    fn test_no_reply() {
+        let mut tty = Canned::new(&[]);
+        let colors = Query::new()
+            .indices(0)
+            .background(false)
+            .run(&mut tty)
+            .unwrap();
+        assert_eq!(tty.written, b"\x1b]10;?\x07\x1b[c");
+        assert_eq!(colors, Colors::default());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color(b"rgb:8/8/8"), Some((0x88, 0x88, 0x88)));
+        assert_eq!(parse_color(b"rgb:7fff/8000/0001"), Some((0x7f, 0x80, 0x00)));
+        for bad in [
+            &b"rgb:1/2"[..],
+            b"rgb:1/2/3/4",
+            b"rgb:12345/0/0",
+            b"rgb://",
+            b"rgb:g/0/0",
+            b"rgb:+f/0/0",
+            b"rgb:0/-0/0",
+        ] {
+            assert_eq!(parse_color(bad), None, "{:?}", std::str::from_utf8(bad));
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Rendering RGB images as text, using Unicode block elements and SGR colors.
+//!
+//! The basic idea (which pretty much every program that shows images in a
+//! terminal ends up reinventing) is that a character cell is about twice as
+//! tall as it is wide, so by printing `▀` with the foreground set to one pixel,
+//! and the background set to the pixel below it, you get two roughly square
+//! pixels per cell.
+//!
+//! This extends that a bit: [`Glyphs::Quadrant`] and [`Glyphs::Sextant`] split
+//! the cell into 2×2 and 2×3 sub-pixels respectively. A cell can still only
+//! have two colors, so for these we find the split of the sub-pixels into a
+//! foreground group and a background group which loses the least detail, and
+//! pick the glyph with the matching shape.
+//!
+//! All averaging (when scaling, or when merging sub-pixels) is done in linear
+//! light, and the results are then quantized with [`nearest_ansi256`] (or
+//! [`nearest_ansi88`], depending on the [`ColorDepth`]), so the output should
+//! look the same as the image does, just blurrier.
+//!
+//! [`nearest_ansi256`]: crate::nearest_ansi256
+//! [`nearest_ansi88`]: crate::nearest_ansi88
+//!
+//! Output is written to a [`core::fmt::Write`], so this doesn't need `std` (or
+//! even `alloc`).
+use crate::imp::oklab::{linear_to_srgb8, srgb8_to_linear};
+use crate::sgr::{Color, Style, RESET};
+use crate::ColorDepth;
+use core::fmt::{self, Write};
+
+/// A borrowed RGB image, stored as packed, row-major, 8-bit sRGB triples
+/// (which is what you'll get from most image decoders for an RGB8 image).
+#[derive(Clone, Copy, Debug)]
+pub struct Image<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Image<'a> {
+    /// Wrap `data`, which holds `width * height` pixels, each of which is 3
+    /// bytes long.
+    ///
+    /// # Panics
+    ///
+    /// If `data.len()` is not `width * height * 3`.
+    #[inline]
+    #[track_caller]
+    pub fn from_rgb8(data: &'a [u8], width: usize, height: usize) -> Self {
+        let len = width.checked_mul(height).and_then(|n| n.checked_mul(3));
+        assert_eq!(
+            len,
+            Some(data.len()),
+            "wrong length for a {width}x{height} RGB8 image",
+        );
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Iterate over the pixels, in row-major order.
+    #[inline]
+    pub fn pixels(&self) -> impl Iterator<Item = (u8, u8, u8)> + 'a {
+        self.data.chunks_exact(3).map(|p| (p[0], p[1], p[2]))
+    }
+
+    #[inline]
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let i = (y * self.width + x) * 3;
+        (self.data[i], self.data[i + 1], self.data[i + 2])
+    }
+}
+
+/// Which characters are used to draw each cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Glyphs {
+    /// `▀`, giving 1×2 pixels per cell. This is supported by basically every
+    /// font.
+    #[default]
+    HalfBlock,
+    /// The quadrant block elements (`▘`, `▚`, `▟`, ...), giving 2×2 pixels per
+    /// cell.
+    Quadrant,
+    /// The sextant characters from the "Symbols for Legacy Computing" block,
+    /// giving 2×3 pixels per cell. Font support for these is spottier.
+    Sextant,
+}
+
+impl Glyphs {
+    /// Size of a cell in sub-pixels, as `(width, height)`.
+    #[inline]
+    const fn cell_size(self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Quadrant => (2, 2),
+            Glyphs::Sextant => (2, 3),
+        }
+    }
+
+    /// Returns the glyph that draws the foreground over the sub-pixels with
+    /// bits set in `mask` (bits are in row-major order).
+    fn glyph(self, mask: u8) -> char {
+        match self {
+            Glyphs::HalfBlock => '▀',
+            Glyphs::Quadrant => QUADRANTS[mask as usize & 0xf],
+            Glyphs::Sextant => match mask & 0x3f {
+                0 => ' ',
+                0b010101 => '▌',
+                0b101010 => '▐',
+                0b111111 => '█',
+                // The sextant block is in mask order, except that it skips the
+                // masks that already have a glyph elsewhere.
+                m => {
+                    let skip = (m > 0b010101) as u32 + (m > 0b101010) as u32;
+                    char::from_u32(0x1fb00 + m as u32 - 1 - skip).unwrap_or('█')
+                }
+            },
+        }
+    }
+}
+
+#[rustfmt::skip]
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛',
+    '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// How colors that fall between palette entries are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Dither {
+    /// Each color just becomes its nearest palette entry.
+    #[default]
+    None,
+    /// Ordered dithering with a 4×4 Bayer matrix. This needs no extra memory
+    /// (unlike error diffusion), and is stable if the image is redrawn.
+    ///
+    /// This does nothing for [`ColorDepth::TrueColor`].
+    Ordered,
+}
+
+/// Renders [`Image`]s as rows of colored glyphs.
+///
+/// ```
+/// use termpal::render::{Glyphs, Image, Renderer};
+/// use termpal::ColorDepth;
+///
+/// let pixels = [0xff, 0x00, 0x00, 0x00, 0x00, 0xff];
+/// let image = Image::from_rgb8(&pixels, 1, 2);
+/// let mut out = String::new();
+/// Renderer::new(ColorDepth::Ansi256)
+///     .glyphs(Glyphs::HalfBlock)
+///     .render(&image, &mut out)
+///     .unwrap();
+/// assert_eq!(out, "\x1b[38;5;196;48;5;21m▀\x1b[0m\n");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Renderer {
+    depth: ColorDepth,
+    glyphs: Glyphs,
+    dither: Dither,
+    columns: Option<usize>,
+}
+
+impl Renderer {
+    /// A renderer that emits colors for `depth`, using half blocks, no
+    /// dithering, and no scaling.
+    #[inline]
+    pub const fn new(depth: ColorDepth) -> Self {
+        Self {
+            depth,
+            glyphs: Glyphs::HalfBlock,
+            dither: Dither::None,
+            columns: None,
+        }
+    }
+
+    #[inline]
+    pub const fn glyphs(mut self, glyphs: Glyphs) -> Self {
+        self.glyphs = glyphs;
+        self
+    }
+
+    #[inline]
+    pub const fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Scale the image so that it's `columns` cells wide. The height is
+    /// chosen to preserve the aspect ratio, assuming cells are twice as tall
+    /// as they are wide.
+    ///
+    /// By default, there's one sub-pixel per pixel horizontally.
+    #[inline]
+    pub const fn columns(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// Write `image` to `out`. Each row of cells ends with a reset (`\x1b[0m`)
+    /// followed by a newline.
+    pub fn render<W: Write + ?Sized>(&self, image: &Image<'_>, out: &mut W) -> fmt::Result {
+        let (w, h) = (image.width, image.height);
+        if w == 0 || h == 0 {
+            return Ok(());
+        }
+        let (cw, ch) = self.glyphs.cell_size();
+        let cols = self.columns.unwrap_or(w.div_ceil(cw)).max(1);
+        let sub_w = cols * cw;
+        // Sub-pixels are `1/cw` wide and `2/ch` tall (in units of the cell
+        // width), so this keeps the aspect ratio. Rounded to nearest.
+        let sub_h = ((h * sub_w * ch + w * cw) / (w * 2 * cw)).max(1);
+        let rows = sub_h.div_ceil(ch);
+        let sampler = Sampler {
+            image,
+            sub_w,
+            sub_h,
+        };
+
+        let mut px = [[0.0f32; 3]; 6];
+        for row in 0..rows {
+            let mut prev = (None, None);
+            for col in 0..cols {
+                for dy in 0..ch {
+                    for dx in 0..cw {
+                        px[dy * cw + dx] = sampler.sample(col * cw + dx, row * ch + dy);
+                    }
+                }
+                let (glyph, fg, bg) = self.cell(&px[..cw * ch], col, row);
+                let style = Style {
+                    fg: fg.filter(|&c| prev.0 != Some(c)),
+                    bg: Some(bg).filter(|&c| prev.1 != Some(c)),
+                    ..Style::new()
+                };
+                style.write_to(self.depth, out)?;
+                prev = (style.fg.or(prev.0), style.bg.or(prev.1));
+                out.write_char(glyph)?;
+            }
+            out.write_str(RESET)?;
+            out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    /// Pick the glyph and colors for a single cell, given its sub-pixels (in
+    /// linear RGB). Returns `None` for the foreground if it's not visible.
+    fn cell(&self, px: &[[f32; 3]], col: usize, row: usize) -> (char, Option<Color>, Color) {
+        if let [top, bottom] = px {
+            let fg = self.quantize(*top, col, row * 2);
+            let bg = self.quantize(*bottom, col, row * 2 + 1);
+            return (self.glyphs.glyph(1), Some(fg), bg);
+        }
+        // Find the split into two groups with the least squared error. The
+        // last sub-pixel always goes in the background group, since otherwise
+        // we'd try every split twice (once with the groups swapped).
+        let n = px.len();
+        let mut best = (f32::MAX, 0u8, [0.0; 3], [0.0; 3]);
+        for mask in 0..(1u8 << (n - 1)) {
+            let mut sums = [[0.0f32; 3]; 2];
+            let mut counts = [0.0f32; 2];
+            for (i, p) in px.iter().enumerate() {
+                let g = (mask >> i) as usize & 1;
+                counts[g] += 1.0;
+                for c in 0..3 {
+                    sums[g][c] += p[c];
+                }
+            }
+            let mean = |g: usize| -> [f32; 3] {
+                let n = counts[g].max(1.0);
+                [sums[g][0] / n, sums[g][1] / n, sums[g][2] / n]
+            };
+            let means = [mean(0), mean(1)];
+            let mut err = 0.0;
+            for (i, p) in px.iter().enumerate() {
+                let m = &means[(mask >> i) as usize & 1];
+                err += (0..3).map(|c| (p[c] - m[c]) * (p[c] - m[c])).sum::<f32>();
+            }
+            if err < best.0 {
+                best = (err, mask, means[1], means[0]);
+            }
+        }
+        let (_, mask, fg, bg) = best;
+        let glyph = self.glyphs.glyph(mask);
+        let bg = self.quantize(bg, col * 2 + 1, row * 2 + 1);
+        if mask == 0 {
+            (glyph, None, bg)
+        } else {
+            (glyph, Some(self.quantize(fg, col * 2, row * 2)), bg)
+        }
+    }
+
+    fn quantize(&self, linear: [f32; 3], x: usize, y: usize) -> Color {
+        let mut rgb = linear.map(linear_to_srgb8);
+        if self.dither == Dither::Ordered && self.depth != ColorDepth::TrueColor {
+            // Spread over about one step of the 6×6×6 cube (which is 40 for
+            // all but the first step).
+            let t = BAYER4[y % 4][x % 4] as i32;
+            let offset = ((2 * t + 1) * 40) / 32 - 20;
+            rgb = rgb.map(|c| (c as i32 + offset).clamp(0, 255) as u8);
+        }
+        let [r, g, b] = rgb;
+        Color::Rgb(r, g, b).to_depth(self.depth)
+    }
+}
+
+#[rustfmt::skip]
+const BAYER4: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
+/// Box-filters the image down (or up) to `sub_w × sub_h` sub-pixels.
+struct Sampler<'a, 'b> {
+    image: &'b Image<'a>,
+    sub_w: usize,
+    sub_h: usize,
+}
+
+impl Sampler<'_, '_> {
+    /// Returns the average of the pixels covered by sub-pixel `(x, y)`, in
+    /// linear RGB. Rows past the bottom of the image (which happen when the
+    /// height isn't a multiple of the cell height) repeat the last row.
+    fn sample(&self, x: usize, y: usize) -> [f32; 3] {
+        let (w, h) = (self.image.width, self.image.height);
+        let y = y.min(self.sub_h - 1);
+        let x0 = x * w / self.sub_w;
+        let x1 = ((x + 1) * w / self.sub_w).max(x0 + 1);
+        let y0 = y * h / self.sub_h;
+        let y1 = ((y + 1) * h / self.sub_h).max(y0 + 1);
+        let mut sum = [0.0f32; 3];
+        for sy in y0..y1 {
+            for sx in x0..x1 {
+                let (r, g, b) = self.image.pixel(sx, sy);
+                sum[0] += srgb8_to_linear(r);
+                sum[1] += srgb8_to_linear(g);
+                sum[2] += srgb8_to_linear(b);
+            }
+        }
+        let n = ((x1 - x0) * (y1 - y0)) as f32;
+        sum.map(|s| s / n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn test_sextant_glyphs() {
+        let g = Glyphs::Sextant;
+        assert_eq!(g.glyph(0b000001), '\u{1fb00}');
+        assert_eq!(g.glyph(0b010100), '\u{1fb13}');
+        assert_eq!(g.glyph(0b010110), '\u{1fb14}');
+        assert_eq!(g.glyph(0b101011), '\u{1fb28}');
+        assert_eq!(g.glyph(0b111110), '\u{1fb3b}');
+        assert_eq!(g.glyph(0b010101), '▌');
+    }
+
+    #[test]
+    fn test_halfblock() {
+        // 2x3: red, green / blue, white / black, black
+        #[rustfmt::skip]
+        let data = [
+            255, 0, 0,   0, 255, 0,
+            0, 0, 255,   255, 255, 255,
+            0, 0, 0,     0, 0, 0,
+        ];
+        let img = Image::from_rgb8(&data, 2, 3);
+        let mut s = String::new();
+        Renderer::new(ColorDepth::Ansi256)
+            .render(&img, &mut s)
+            .unwrap();
+        assert_eq!(
+            s,
+            "\x1b[38;5;196;48;5;21m▀\x1b[38;5;46;48;5;231m▀\x1b[0m\n\
+             \x1b[38;5;16;48;5;16m▀▀\x1b[0m\n",
+        );
+        let mut s = String::new();
+        Renderer::new(ColorDepth::TrueColor)
+            .render(&img, &mut s)
+            .unwrap();
+        assert!(s.starts_with("\x1b[38;2;255;0;0;48;2;0;0;255m▀"), "{s:?}");
+    }
+
+    #[test]
+    fn test_quadrant_split() {
+        // A 2x4 image is one cell of 2x2 sub-pixels (each covering 1x2
+        // pixels), with only the top-left one white.
+        let mut data = [0u8; 2 * 4 * 3];
+        for y in 0..2 {
+            data[y * 6..y * 6 + 3].copy_from_slice(&[255, 255, 255]);
+        }
+        let img = Image::from_rgb8(&data, 2, 4);
+        let mut s = String::new();
+        Renderer::new(ColorDepth::Ansi256)
+            .glyphs(Glyphs::Quadrant)
+            .columns(1)
+            .render(&img, &mut s)
+            .unwrap();
+        assert_eq!(s, "\x1b[38;5;231;48;5;16m▘\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_scaling() {
+        let data = [0x80u8; 8 * 8 * 3];
+        let img = Image::from_rgb8(&data, 8, 8);
+        let mut s = String::new();
+        Renderer::new(ColorDepth::Ansi256)
+            .columns(4)
+            .dither(Dither::Ordered)
+            .render(&img, &mut s)
+            .unwrap();
+        assert_eq!(s.lines().count(), 2);
+        for line in s.lines() {
+            assert_eq!(line.chars().filter(|&c| c == '▀').count(), 4);
+        }
+    }
+}
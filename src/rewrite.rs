@@ -0,0 +1,707 @@
+//! Rewriting the SGR sequences in a stream of terminal output.
+//!
+//! This is for output that comes from somewhere else (a subprocess, a log
+//! file, ...), and uses colors that the terminal it's going to doesn't
+//! support, or that shouldn't be there at all. A [`Rewriter`] wraps an
+//! [`io::Write`], and changes the colors in every SGR sequence written through
+//! it, according to its [`Mode`]. Everything else is passed through untouched,
+//! including other escape sequences, and the parts of an SGR sequence that
+//! aren't colors (so `4:3` stays a curly underline, even though [`sgr::parse`]
+//! doesn't distinguish underline styles). Sequences that don't parse are left
+//! alone entirely -- the terminal might know what they mean even if we don't.
+//!
+//! The usual case, bringing colors down to what the terminal can show, is a
+//! [`Downgrader`].
+//!
+//! Writes can split a sequence anywhere, so an incomplete sequence at the end
+//! of a write is held until the next one completes it.
+//!
+//! If the underlying writer fails, the output it didn't take is kept, and
+//! written before anything else. The write that produced it has still
+//! succeeded (as with a [`BufWriter`](std::io::BufWriter)), so the error is
+//! reported by the next call to `write` or `flush` instead, and that call
+//! doesn't take any input. So retrying never writes anything twice.
+use crate::palette::Palette;
+use crate::sgr::{self, Color, Event, Layer, Params};
+use crate::ColorDepth;
+use std::io::{self, Write};
+use std::string::String;
+use std::vec::Vec;
+
+/// CSI sequences longer than this are passed through without being looked at,
+/// so that garbage input can't make us buffer forever. Real SGR sequences are
+/// nowhere near this long.
+const MAX_SEQUENCE: usize = 256;
+
+/// What a [`Rewriter`] does to the colors it finds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Convert colors to ones that can be displayed at the given depth, using
+    /// [`Color::to_depth`]. Colors that are already fine are left as they
+    /// were written.
+    Downgrade(ColorDepth),
+    /// Replace indexed colors (including the 16-color forms like `31`) with
+    /// RGB ones, so they look the same regardless of the terminal's palette.
+    ///
+    /// The values come from the [`Rewriter`]'s [`palette`](Rewriter::palette)
+    /// if one was given (and it has an entry for the index), and
+    /// [`ansi256_to_rgb`](crate::ansi256_to_rgb) otherwise.
+    Upgrade,
+    /// Remove all colors, in the spirit of [`NO_COLOR`](https://no-color.org).
+    /// Other attributes, such as bold, are kept, and sequences that only
+    /// contained colors are removed completely.
+    Strip,
+}
+
+/// What to do with a single color in a sequence.
+enum Action {
+    Keep,
+    Remove,
+    Replace(Color, ColorDepth),
+}
+
+impl Mode {
+    fn action(self, color: Color, layer: Layer, raw: &[u8], palette: Option<&Palette>) -> Action {
+        match self {
+            Mode::Downgrade(depth) => {
+                let to = color.to_depth(depth);
+                // At 16 colors, `38;5;1` has to become `31` even though the
+                // color is the same. The short forms never contain a
+                // separator.
+                let extended = raw.iter().any(|&b| b == b';' || b == b':');
+                let named = matches!(to, Color::Indexed(n) if n < 16) && layer != Layer::Underline;
+                if to != color || (depth == ColorDepth::Ansi16 && extended && named) {
+                    Action::Replace(to, depth)
+                } else {
+                    Action::Keep
+                }
+            }
+            Mode::Upgrade => match color {
+                Color::Indexed(n) => {
+                    let (r, g, b) = palette
+                        .and_then(|p| p.get(n))
+                        .unwrap_or_else(|| crate::ansi256_to_rgb(n));
+                    Action::Replace(Color::Rgb(r, g, b), ColorDepth::TrueColor)
+                }
+                _ => Action::Keep,
+            },
+            Mode::Strip => Action::Remove,
+        }
+    }
+}
+
+impl From<ColorDepth> for Mode {
+    /// [`Mode::Downgrade`] to `depth`.
+    #[inline]
+    fn from(depth: ColorDepth) -> Self {
+        Mode::Downgrade(depth)
+    }
+}
+
+/// An [`io::Write`] adapter that rewrites the colors in SGR sequences.
+///
+/// ```
+/// use std::io::Write;
+/// use termpal::rewrite::{Mode, Rewriter};
+/// use termpal::ColorDepth;
+///
+/// let mut w = Rewriter::new(Vec::new(), Mode::Downgrade(ColorDepth::Ansi256));
+/// // Sequences can be split across writes.
+/// w.write_all(b"\x1b[1;38;2;255;1").unwrap();
+/// w.write_all(b"28;0mhi\x1b[0m").unwrap();
+/// let out = w.into_inner().unwrap();
+/// assert_eq!(out, b"\x1b[1;38;5;208mhi\x1b[0m");
+/// ```
+///
+/// If the stream might end partway through an escape sequence, call
+/// [`finish`](Rewriter::finish) or [`into_inner`](Rewriter::into_inner) when
+/// you're done, so the held bytes are written.
+#[derive(Debug)]
+pub struct Rewriter<W: Write> {
+    inner: W,
+    mode: Mode,
+    palette: Option<Palette>,
+    scanner: Scanner,
+    out: String,
+    /// Output the underlying writer hasn't taken yet.
+    pending: Vec<u8>,
+}
+
+/// A [`Rewriter`] that brings colors down to what can be shown at a given
+/// [`ColorDepth`], and leaves everything else alone.
+///
+/// This is the same type, so a `Downgrader` can still be given any other
+/// [`Mode`]; it's just shorter to make one from a depth.
+///
+/// ```
+/// use std::io::Write;
+/// use termpal::rewrite::Downgrader;
+/// use termpal::ColorDepth;
+///
+/// let mut w = Downgrader::new(Vec::new(), ColorDepth::Ansi256);
+/// w.write_all(b"\x1b[38;2;255;128;0mhi\x1b[0m").unwrap();
+/// assert_eq!(w.into_inner().unwrap(), b"\x1b[38;5;208mhi\x1b[0m");
+/// ```
+pub type Downgrader<W> = Rewriter<W>;
+
+impl<W: Write> Rewriter<W> {
+    /// Wrap `inner`, rewriting according to `mode`, which can also be a
+    /// [`ColorDepth`] to downgrade to.
+    #[inline]
+    pub fn new(inner: W, mode: impl Into<Mode>) -> Self {
+        Self {
+            inner,
+            mode: mode.into(),
+            palette: None,
+            scanner: Scanner::default(),
+            out: String::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Set the palette used by [`Mode::Upgrade`], for when the output came
+    /// from a terminal with a different palette than the `xterm-256color`
+    /// defaults. Usually this only needs the 16 named colors, which are the
+    /// ones users customize, as indices past the end of the palette use the
+    /// defaults.
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use termpal::palette::Palette;
+    /// use termpal::rewrite::{Mode, Rewriter};
+    ///
+    /// // Solarized's first two colors.
+    /// let palette = Palette::from_rgb(&[(0x07, 0x36, 0x42), (0xdc, 0x32, 0x2f)]);
+    /// let mut w = Rewriter::new(Vec::new(), Mode::Upgrade).palette(palette);
+    /// w.write_all(b"\x1b[31;48;5;16m").unwrap();
+    /// assert_eq!(w.get_ref(), b"\x1b[38;2;220;50;47;48;2;0;0;0m");
+    /// ```
+    #[inline]
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writing to the underlying writer directly while a sequence (or output
+    /// from after an error) is being held will put the output out of order.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Write any incomplete escape sequence that's being held, as-is.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let pending = &mut self.pending;
+        let _ = self.scanner.finish(|chunk| {
+            pending.extend_from_slice(chunk.bytes());
+            Ok(())
+        });
+        write_pending(pending, &mut self.inner)
+    }
+
+    /// [`finish`](Self::finish), and return the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Rewriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write_pending(&mut self.pending, &mut self.inner)?;
+        let Self {
+            inner,
+            mode,
+            palette,
+            scanner,
+            out,
+            pending,
+        } = self;
+        let _ = scanner.feed(buf, |chunk| {
+            match chunk {
+                Chunk::Sgr { params, .. } if rewrite(params, *mode, palette.as_ref(), out) => {
+                    pending.extend_from_slice(out.as_bytes())
+                }
+                chunk => pending.extend_from_slice(chunk.bytes()),
+            }
+            Ok(())
+        });
+        // `buf` is ours now, so an error is left for the next call.
+        let _ = write_pending(pending, inner);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        write_pending(&mut self.pending, &mut self.inner)?;
+        self.inner.flush()
+    }
+}
+
+/// Write as much of `pending` to `inner` as it'll take, removing what was
+/// written, and stopping at the first error (other than `Interrupted`).
+pub(crate) fn write_pending(pending: &mut Vec<u8>, inner: &mut impl Write) -> io::Result<()> {
+    let mut written = 0;
+    let res = loop {
+        if written == pending.len() {
+            break Ok(());
+        }
+        match inner.write(&pending[written..]) {
+            Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    pending.drain(..written);
+    res
+}
+
+/// Write `params` to `out` as a complete SGR sequence (or nothing, if every
+/// parameter was removed), with its colors rewritten for `mode`. Returns false
+/// if the original sequence should be used instead, either because nothing
+/// needed changing, or because it didn't parse.
+fn rewrite(params: &[u8], mode: Mode, palette: Option<&Palette>, out: &mut String) -> bool {
+    out.clear();
+    let mut changed = false;
+    let mut p = Params::new(out);
+    let mut parser = sgr::parse(params);
+    while let Some((event, raw)) = parser.next_with_raw() {
+        let Ok(event) = event else {
+            return false;
+        };
+        let color = match event {
+            Event::Fg(c) => Some((c, Layer::Fg)),
+            Event::Bg(c) => Some((c, Layer::Bg)),
+            Event::UnderlineColor(c) => Some((c, Layer::Underline)),
+            _ => None,
+        };
+        match color.map(|(c, layer)| (mode.action(c, layer, raw, palette), layer)) {
+            None | Some((Action::Keep, _)) => {
+                // It parsed, so it's all digits, `;` and `:`.
+                let _ = p.raw(core::str::from_utf8(raw).unwrap_or_default());
+            }
+            Some((Action::Remove, _)) => changed = true,
+            Some((Action::Replace(color, depth), layer)) => {
+                changed = true;
+                let _ = sgr::write_color(&mut p, color, layer, depth);
+            }
+        }
+    }
+    let _ = p.finish();
+    changed
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    /// After an `ESC`, and possibly some intermediate bytes.
+    Escape,
+    /// After `ESC [`.
+    Csi,
+    /// In a CSI sequence that was too long, which is passed on up to and
+    /// including its final byte.
+    Skip,
+    /// In a control string (such as an OSC), which ends with `BEL` or `ESC \`.
+    /// These can be long (OSC 52 carries the whole clipboard), so they're
+    /// passed on as they arrive, rather than held.
+    String,
+}
+
+/// A piece of the output, as split up by the [`Scanner`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Chunk<'a> {
+    /// Text to be displayed.
+    Text(&'a [u8]),
+    /// An escape sequence other than SGR, or part of one. Control strings are
+    /// passed on as they arrive, and malformed sequences wherever they're
+    /// given up on, so these are only really useful for passing through.
+    Escape(&'a [u8]),
+    /// A complete SGR sequence (`seq`), and its parameters.
+    Sgr { seq: &'a [u8], params: &'a [u8] },
+}
+
+impl<'a> Chunk<'a> {
+    #[inline]
+    pub(crate) fn bytes(self) -> &'a [u8] {
+        match self {
+            Chunk::Text(b) | Chunk::Escape(b) | Chunk::Sgr { seq: b, .. } => b,
+        }
+    }
+}
+
+/// Finds the escape sequences in a stream of bytes that may be split
+/// anywhere.
+///
+/// Only the 7-bit forms (`ESC [` and so on) are recognized, as the 8-bit C1
+/// controls are UTF-8 continuation bytes, and the output is almost certainly
+/// UTF-8.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Scanner {
+    state: State,
+    /// The incomplete sequence, if any.
+    pending: Vec<u8>,
+}
+
+impl Scanner {
+    /// Split `buf` into [`Chunk`]s, passing each one to `f`. The bytes of the
+    /// chunks are always the input bytes, in the same order.
+    pub(crate) fn feed(
+        &mut self,
+        mut buf: &[u8],
+        mut f: impl FnMut(Chunk<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        while let Some(&b) = buf.first() {
+            match self.state {
+                State::Ground => {
+                    let i = buf.iter().position(|&b| b == 0x1b).unwrap_or(buf.len());
+                    if i != 0 {
+                        f(Chunk::Text(&buf[..i]))?;
+                    }
+                    if i != buf.len() {
+                        self.pending.push(0x1b);
+                        self.state = State::Escape;
+                        buf = &buf[i + 1..];
+                    } else {
+                        buf = &[];
+                    }
+                    continue;
+                }
+                State::Skip => {
+                    let i = buf.iter().position(|b| (0x40..=0x7e).contains(b));
+                    let end = i.map_or(buf.len(), |i| i + 1);
+                    f(Chunk::Escape(&buf[..end]))?;
+                    if i.is_some() {
+                        self.state = State::Ground;
+                    }
+                    buf = &buf[end..];
+                    continue;
+                }
+                State::String => {
+                    let i = buf.iter().position(|&b| b == 0x07 || b == 0x1b);
+                    // An `ESC` is left for `Ground` to start a new sequence
+                    // with. If it's `ESC \` that will be the end of this one,
+                    // and if it's not, the string was cancelled.
+                    let end = match i {
+                        Some(i) if buf[i] == 0x07 => i + 1,
+                        Some(i) => i,
+                        None => buf.len(),
+                    };
+                    if end != 0 {
+                        f(Chunk::Escape(&buf[..end]))?;
+                    }
+                    if i.is_some() {
+                        self.state = State::Ground;
+                    }
+                    buf = &buf[end..];
+                    continue;
+                }
+                State::Escape if self.pending.len() == 1 && b == b'[' => {
+                    self.pending.push(b);
+                    self.state = State::Csi;
+                }
+                State::Escape if self.pending.len() == 1 && b"]P_^X".contains(&b) => {
+                    self.pending.push(b);
+                    self.state = State::String;
+                    self.flush(&mut f)?;
+                }
+                State::Escape
+                    if (0x20..=0x2f).contains(&b) && self.pending.len() < MAX_SEQUENCE =>
+                {
+                    self.pending.push(b);
+                }
+                State::Escape if (0x30..=0x7e).contains(&b) => {
+                    self.pending.push(b);
+                    self.state = State::Ground;
+                    self.flush(&mut f)?;
+                }
+                State::Csi if (0x20..=0x3f).contains(&b) => {
+                    self.pending.push(b);
+                    if self.pending.len() >= MAX_SEQUENCE {
+                        self.state = State::Skip;
+                        self.flush(&mut f)?;
+                    }
+                }
+                State::Csi if (0x40..=0x7e).contains(&b) => {
+                    self.pending.push(b);
+                    self.state = State::Ground;
+                    let seq = &self.pending[..];
+                    let params = &seq[2..seq.len() - 1];
+                    let sgr = b == b'm'
+                        && params
+                            .iter()
+                            .all(|&b| b.is_ascii_digit() || b == b';' || b == b':');
+                    let res = f(if sgr {
+                        Chunk::Sgr { seq, params }
+                    } else {
+                        Chunk::Escape(seq)
+                    });
+                    self.pending.clear();
+                    res?;
+                }
+                State::Escape | State::Csi => {
+                    // Something that can't appear in the sequence (usually
+                    // another `ESC`, or a control character), so give up on
+                    // it.
+                    self.state = State::Ground;
+                    self.flush(&mut f)?;
+                    if b == 0x1b {
+                        self.pending.push(b);
+                        self.state = State::Escape;
+                    } else {
+                        f(Chunk::Text(&buf[..1]))?;
+                    }
+                }
+            }
+            buf = &buf[1..];
+        }
+        Ok(())
+    }
+
+    /// Pass on the incomplete sequence.
+    fn flush(&mut self, mut f: impl FnMut(Chunk<'_>) -> io::Result<()>) -> io::Result<()> {
+        let res = if self.pending.is_empty() {
+            Ok(())
+        } else {
+            f(Chunk::Escape(&self.pending))
+        };
+        self.pending.clear();
+        res
+    }
+
+    /// Pass on any incomplete sequence, and return to the initial state.
+    pub(crate) fn finish(&mut self, f: impl FnMut(Chunk<'_>) -> io::Result<()>) -> io::Result<()> {
+        self.state = State::Ground;
+        self.flush(f)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// A writer that takes at most 3 bytes at a time, and fails once, on its
+    /// `fail_at`th call (counting from 0).
+    pub(crate) struct Flaky {
+        pub(crate) out: Vec<u8>,
+        pub(crate) calls: usize,
+        fail_at: usize,
+    }
+
+    impl Flaky {
+        pub(crate) fn new(fail_at: usize) -> Self {
+            Self {
+                out: Vec::new(),
+                calls: 0,
+                fail_at,
+            }
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == self.fail_at + 1 {
+                return Err(io::Error::other("flaky"));
+            }
+            let n = buf.len().min(3);
+            self.out.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Write `input` to `w` in chunks of 4, retrying after errors the way a
+    /// caller would, and flush.
+    pub(crate) fn write_retrying(w: &mut impl Write, input: &[u8]) {
+        for chunk in input.chunks(4) {
+            let mut rest = chunk;
+            while !rest.is_empty() {
+                if let Ok(n) = w.write(rest) {
+                    rest = &rest[n..];
+                }
+            }
+        }
+        while w.flush().is_err() {}
+    }
+
+    /// Write `input` to a `Rewriter` one write per chunk, split at every
+    /// possible position, and check that it's always the same.
+    fn rewrite(input: &[u8], mode: Mode) -> Vec<u8> {
+        let whole = {
+            let mut w = Rewriter::new(Vec::new(), mode);
+            w.write_all(input).unwrap();
+            w.into_inner().unwrap()
+        };
+        for size in 1..input.len() {
+            let mut w = Rewriter::new(Vec::new(), mode);
+            for chunk in input.chunks(size) {
+                w.write_all(chunk).unwrap();
+            }
+            assert_eq!(w.into_inner().unwrap(), whole, "chunks of {size}");
+        }
+        whole
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let input = b"a\x1b[1;38;2;255;0;0;48:2::0:0:255mb\x1b[0mc";
+        assert_eq!(
+            rewrite(input, Mode::Downgrade(ColorDepth::Ansi256)),
+            b"a\x1b[1;38;5;196;48;5;21mb\x1b[0mc"
+        );
+        assert_eq!(
+            rewrite(input, Mode::Downgrade(ColorDepth::Ansi16)),
+            b"a\x1b[1;91;104mb\x1b[0mc"
+        );
+        assert_eq!(
+            rewrite(input, Mode::Downgrade(ColorDepth::TrueColor)),
+            input
+        );
+        // Only the colors are rewritten.
+        assert_eq!(
+            rewrite(
+                b"\x1b[4:3;38;2;255;0;0;;10;58:5:200m",
+                Mode::Downgrade(ColorDepth::Ansi256)
+            ),
+            b"\x1b[4:3;38;5;196;;10;58:5:200m"
+        );
+        // Indexed colors need the short forms at 16 colors.
+        assert_eq!(
+            rewrite(
+                b"\x1b[38;5;1;48:5:200;58;5;3;31m",
+                Mode::Downgrade(ColorDepth::Ansi16)
+            ),
+            b"\x1b[31;105;58;5;3;31m"
+        );
+    }
+
+    #[test]
+    fn test_upgrade() {
+        assert_eq!(
+            rewrite(b"\x1b[1;31;48;5;196;58:5:232;39mx", Mode::Upgrade),
+            b"\x1b[1;38;2;128;0;0;48;2;255;0;0;58;2;8;8;8;39mx"
+        );
+        let input = b"\x1b[38;2;1;2;3;4m";
+        assert_eq!(rewrite(input, Mode::Upgrade), input);
+
+        let mut palette = Palette::from_rgb(&[(1, 1, 1); 16]);
+        palette.set(9, (10, 20, 30));
+        let mut w = Rewriter::new(Vec::new(), Mode::Upgrade).palette(palette);
+        w.write_all(b"\x1b[91;100;38:5:196m").unwrap();
+        assert_eq!(
+            w.into_inner().unwrap(),
+            b"\x1b[38;2;10;20;30;48;2;1;1;1;38;2;255;0;0m"
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(
+            rewrite(
+                b"a\x1b[31mb\x1b[1;38;2;1;2;3;4:3mc\x1b[0m\x1b[m",
+                Mode::Strip
+            ),
+            b"ab\x1b[1;4:3mc\x1b[0m\x1b[m"
+        );
+        // Still left alone, as we can't tell what it's doing.
+        let input = b"\x1b[1;38;5m";
+        assert_eq!(rewrite(input, Mode::Strip), input);
+    }
+
+    #[test]
+    fn test_write_errors() {
+        let input = b"a\x1b[1;38;2;255;0;0mbcdef\x1b[0mg\x1b]0;t\x07h\x1b[38;2";
+        let mode = Mode::Downgrade(ColorDepth::Ansi256);
+        let want = rewrite(input, mode);
+        for fail_at in 0..12 {
+            let mut w = Rewriter::new(Flaky::new(fail_at), mode);
+            write_retrying(&mut w, input);
+            // Make sure it actually failed.
+            assert!(w.get_ref().calls > fail_at);
+            let out = w.into_inner().unwrap().out;
+            assert_eq!(out, want, "failing call {fail_at}");
+        }
+    }
+
+    #[test]
+    fn test_scanner() {
+        let input = b"a\x1b[1mb\x1b[2Jc\x1b]0;t\x07d\x1b]8;;x\x1b\\e\x1b(Bf\x1b\x1b[\n";
+        let mut chunks: Vec<(char, Vec<u8>)> = Vec::new();
+        let mut scanner = Scanner::default();
+        // Byte by byte, so the text is easier to put back together.
+        for b in input.chunks(1) {
+            scanner
+                .feed(b, |c| {
+                    let kind = match c {
+                        Chunk::Text(_) => 't',
+                        Chunk::Escape(_) => 'e',
+                        Chunk::Sgr { .. } => 's',
+                    };
+                    match chunks.last_mut() {
+                        Some((k, bytes)) if *k == kind && kind != 's' => {
+                            bytes.extend_from_slice(c.bytes())
+                        }
+                        _ => chunks.push((kind, c.bytes().to_vec())),
+                    }
+                    Ok(())
+                })
+                .unwrap();
+        }
+        let want: &[(char, &[u8])] = &[
+            ('t', b"a"),
+            ('s', b"\x1b[1m"),
+            ('t', b"b"),
+            ('e', b"\x1b[2J"),
+            ('t', b"c"),
+            ('e', b"\x1b]0;t\x07"),
+            ('t', b"d"),
+            ('e', b"\x1b]8;;x\x1b\\"),
+            ('t', b"e"),
+            ('e', b"\x1b(B"),
+            ('t', b"f"),
+            ('e', b"\x1b\x1b["),
+            ('t', b"\n"),
+        ];
+        let got: Vec<_> = chunks.iter().map(|(k, b)| (*k, &b[..])).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let inputs: &[&[u8]] = &[
+            b"plain text, \xe2\x9c\x93 \x9b1m",
+            b"\x1b[2J\x1b[?25l\x1b[1;2H\x1b[>4;1m",
+            b"\x1b]0;title\x07\x1b]11;rgb:0/0/0\x1b\\",
+            b"\x1b\x1b[1m\x1b7\x1b[1\x07m",
+            // Malformed, but the terminal can deal with it.
+            b"\x1b[38;2;1m\x1b[38;6;1m\x1b[99999m",
+            b"trailing \x1b[38;2",
+            b"\x1b",
+        ];
+        for &input in inputs {
+            assert_eq!(rewrite(input, Mode::Downgrade(ColorDepth::Ansi16)), input);
+        }
+        let mut long = b"\x1b[".to_vec();
+        long.resize(MAX_SEQUENCE * 2, b'1');
+        long.extend_from_slice(b";38;2;1;2;3mx\x1b[38;2;255;0;0m");
+        let mut want = long.clone();
+        want.truncate(want.len() - b"38;2;255;0;0m".len());
+        want.extend_from_slice(b"38;5;196m");
+        assert_eq!(rewrite(&long, Mode::Downgrade(ColorDepth::Ansi256)), want);
+    }
+}
@@ -0,0 +1,463 @@
+//! Formatting SGR ("Select Graphic Rendition") escape sequences, the
+//! `\x1b[...m` sequences that terminals use to set colors and text attributes.
+//!
+//! The main thing this adds over writing the sequences by hand is that colors
+//! are converted to whatever the terminal supports: the same [`Style`] will
+//! produce `38;2;r;g;b` for a true color terminal, `38;5;n` (using
+//! [`nearest_ansi256`]) for a 256-color one, and `30`-`37`/`90`-`97` for one
+//! that only has the 16 named colors.
+//!
+//! [`nearest_ansi256`]: crate::nearest_ansi256
+//!
+//! ```
+//! use termpal::sgr::{Attrs, Color, Style};
+//! use termpal::ColorDepth;
+//!
+//! let style = Style::new()
+//!     .fg(Color::Rgb(0xff, 0x80, 0x00))
+//!     .attrs(Attrs::BOLD);
+//! assert_eq!(
+//!     style.display(ColorDepth::TrueColor).to_string(),
+//!     "\x1b[1;38;2;255;128;0m",
+//! );
+//! assert_eq!(style.display(ColorDepth::Ansi256).to_string(), "\x1b[1;38;5;208m");
+//! assert_eq!(style.display(ColorDepth::Ansi16).to_string(), "\x1b[1;91m");
+//! ```
+//!
+//! Going the other way, [`parse`] turns the parameters of an SGR sequence
+//! (such as one emitted by another program) into [`Event`]s, which can then be
+//! re-emitted for a different depth.
+//!
+//! Everything here is `no_std`, and never allocates.
+use crate::imp::tab;
+use crate::ColorDepth;
+use core::fmt::{self, Write};
+
+mod parse;
+pub use parse::{parse, parse_sequence, Error, Event, Parser};
+
+/// The sequence which resets all colors and attributes to their defaults.
+pub const RESET: &str = "\x1b[0m";
+
+/// A color, as used in an SGR sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// The terminal's default color for the layer (`39`, `49` or `59`).
+    Default,
+    /// An index into the terminal's palette. `0..16` are the named colors,
+    /// which are usually customized by the user, and the rest are the
+    /// `xterm-256color` cube and grey ramp.
+    Indexed(u8),
+    /// A 24-bit sRGB color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Convert to the closest color that can be displayed at `depth`.
+    ///
+    /// Indexed colors are left alone at [`ColorDepth::TrueColor`] and
+    /// [`ColorDepth::Ansi256`], as the terminal knows what its palette looks
+    /// like better than we do. At [`ColorDepth::Ansi16`] this always returns
+    /// a color in `Indexed(0..16)` (or `Default`).
+    pub fn to_depth(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Default, _) | (_, ColorDepth::TrueColor) => self,
+            (Color::Indexed(_), ColorDepth::Ansi256) => self,
+            (Color::Rgb(r, g, b), ColorDepth::Ansi256) => {
+                Color::Indexed(crate::nearest_ansi256(r, g, b))
+            }
+            #[cfg(feature = "88color")]
+            (Color::Indexed(n), ColorDepth::Ansi88) if n >= 16 => {
+                Color::Indexed(tab::ANSI256_TO_88[n as usize - 16])
+            }
+            #[cfg(feature = "88color")]
+            (Color::Indexed(_), ColorDepth::Ansi88) => self,
+            #[cfg(feature = "88color")]
+            (Color::Rgb(r, g, b), ColorDepth::Ansi88) => {
+                Color::Indexed(crate::nearest_ansi88(r, g, b))
+            }
+            (Color::Indexed(n), ColorDepth::Ansi16) => Color::Indexed(tab::ansi256_to_named(n)),
+            (Color::Rgb(r, g, b), ColorDepth::Ansi16) => {
+                Color::Indexed(tab::ansi256_to_named(crate::nearest_ansi256(r, g, b)))
+            }
+        }
+    }
+}
+
+impl From<(u8, u8, u8)> for Color {
+    #[inline]
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Color::Rgb(r, g, b)
+    }
+}
+
+/// A set of text attributes, such as bold or underline.
+///
+/// Combine these with `|`, e.g. `Attrs::BOLD | Attrs::ITALIC`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Attrs(u16);
+
+impl Attrs {
+    pub const NONE: Attrs = Attrs(0);
+    pub const BOLD: Attrs = Attrs(1 << 1);
+    pub const DIM: Attrs = Attrs(1 << 2);
+    pub const ITALIC: Attrs = Attrs(1 << 3);
+    pub const UNDERLINE: Attrs = Attrs(1 << 4);
+    pub const BLINK: Attrs = Attrs(1 << 5);
+    pub const REVERSE: Attrs = Attrs(1 << 7);
+    pub const HIDDEN: Attrs = Attrs(1 << 8);
+    pub const STRIKETHROUGH: Attrs = Attrs(1 << 9);
+
+    // Bit `n` is the attribute set by SGR parameter `n`, which keeps this
+    // simple.
+    const ALL: u16 = 0b11_1011_1110;
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub const fn contains(self, o: Attrs) -> bool {
+        (self.0 & o.0) == o.0
+    }
+
+    #[inline]
+    pub const fn union(self, o: Attrs) -> Attrs {
+        Attrs(self.0 | o.0)
+    }
+
+    #[inline]
+    pub const fn difference(self, o: Attrs) -> Attrs {
+        Attrs(self.0 & !o.0)
+    }
+
+    /// The attribute enabled by SGR parameter `n`, if any.
+    #[inline]
+    pub const fn from_sgr(n: u16) -> Option<Attrs> {
+        if n < 16 && (Self::ALL & (1 << n)) != 0 {
+            Some(Attrs(1 << n))
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the SGR parameters that enable these attributes.
+    #[inline]
+    fn params(self) -> impl Iterator<Item = u16> {
+        (1..16).filter(move |&n| (self.0 & (1 << n)) != 0)
+    }
+}
+
+impl core::ops::BitOr for Attrs {
+    type Output = Attrs;
+    #[inline]
+    fn bitor(self, o: Attrs) -> Attrs {
+        self.union(o)
+    }
+}
+
+impl core::ops::BitOrAssign for Attrs {
+    #[inline]
+    fn bitor_assign(&mut self, o: Attrs) {
+        *self = self.union(o);
+    }
+}
+
+/// A set of colors and attributes, which can be written as a single SGR
+/// sequence.
+///
+/// Colors which are `None` are left as they are (that is, no parameter is
+/// emitted for them). Use [`Color::Default`] to explicitly reset a color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub underline_color: Option<Color>,
+    pub attrs: Attrs,
+}
+
+impl Style {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            fg: None,
+            bg: None,
+            underline_color: None,
+            attrs: Attrs::NONE,
+        }
+    }
+
+    #[inline]
+    pub const fn fg(mut self, c: Color) -> Self {
+        self.fg = Some(c);
+        self
+    }
+
+    #[inline]
+    pub const fn bg(mut self, c: Color) -> Self {
+        self.bg = Some(c);
+        self
+    }
+
+    #[inline]
+    pub const fn underline_color(mut self, c: Color) -> Self {
+        self.underline_color = Some(c);
+        self
+    }
+
+    #[inline]
+    pub const fn attrs(mut self, a: Attrs) -> Self {
+        self.attrs = self.attrs.union(a);
+        self
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.fg.is_none()
+            && self.bg.is_none()
+            && self.underline_color.is_none()
+            && self.attrs.is_empty()
+    }
+
+    /// Update the style for an [`Event`] from the [`Parser`], as a terminal
+    /// would. This treats `self` as the complete current state, so colors
+    /// reset to the default become `None`.
+    ///
+    /// ```
+    /// use termpal::sgr::{parse, Attrs, Color, Style};
+    ///
+    /// let mut style = Style::new();
+    /// for event in parse(b"1;31;44;39") {
+    ///     style.apply(event.unwrap());
+    /// }
+    /// assert_eq!(style, Style::new().bg(Color::Indexed(4)).attrs(Attrs::BOLD));
+    /// ```
+    pub fn apply(&mut self, event: Event) {
+        let color = |c| Some(c).filter(|&c| c != Color::Default);
+        match event {
+            Event::Reset => *self = Style::new(),
+            Event::Set(a) => self.attrs |= a,
+            Event::Unset(a) => self.attrs = self.attrs.difference(a),
+            Event::Fg(c) => self.fg = color(c),
+            Event::Bg(c) => self.bg = color(c),
+            Event::UnderlineColor(c) => self.underline_color = color(c),
+            Event::Other(_) => {}
+        }
+    }
+
+    /// Write this style as an SGR sequence, with colors converted for
+    /// `depth`. Nothing is written for an empty style.
+    ///
+    /// Note that the sequence only turns attributes on: it doesn't reset the
+    /// ones that aren't present (write [`RESET`] first if you need that).
+    pub fn write_to<W: Write + ?Sized>(&self, depth: ColorDepth, out: &mut W) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut p = Params::new(out);
+        for n in self.attrs.params() {
+            p.param(n)?;
+        }
+        let layers = [
+            (self.fg, Layer::Fg),
+            (self.bg, Layer::Bg),
+            (self.underline_color, Layer::Underline),
+        ];
+        for (c, layer) in layers {
+            if let Some(c) = c {
+                write_color(&mut p, c.to_depth(depth), layer, depth)?;
+            }
+        }
+        p.finish()
+    }
+
+    /// Returns something that [`Display`](fmt::Display)s as
+    /// [`write_to`](Style::write_to) would write.
+    #[inline]
+    pub const fn display(&self, depth: ColorDepth) -> StyleDisplay {
+        StyleDisplay {
+            style: *self,
+            depth,
+        }
+    }
+}
+
+/// The result of [`Style::display`].
+#[derive(Clone, Copy, Debug)]
+pub struct StyleDisplay {
+    style: Style,
+    depth: ColorDepth,
+}
+
+impl fmt::Display for StyleDisplay {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.style.write_to(self.depth, f)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Layer {
+    Fg,
+    Bg,
+    Underline,
+}
+
+impl Layer {
+    /// The parameter that introduces an extended color for this layer, e.g.
+    /// the `38` in `38;5;n`.
+    #[inline]
+    fn extended(self) -> u16 {
+        match self {
+            Layer::Fg => 38,
+            Layer::Bg => 48,
+            Layer::Underline => 58,
+        }
+    }
+}
+
+/// `color` must already have been converted with [`Color::to_depth`].
+pub(crate) fn write_color<W: Write + ?Sized>(
+    p: &mut Params<'_, W>,
+    color: Color,
+    layer: Layer,
+    depth: ColorDepth,
+) -> fmt::Result {
+    let ext = layer.extended();
+    match color {
+        Color::Default => p.param(ext + 1),
+        // Underline color doesn't have a 16-color form.
+        Color::Indexed(n) if n < 16 && depth == ColorDepth::Ansi16 && layer != Layer::Underline => {
+            let base = if layer == Layer::Fg { 30 } else { 40 };
+            p.param(if n < 8 {
+                base + n as u16
+            } else {
+                base + 60 + (n - 8) as u16
+            })
+        }
+        Color::Indexed(n) => {
+            p.param(ext)?;
+            p.param(5)?;
+            p.param(n.into())
+        }
+        Color::Rgb(r, g, b) => {
+            p.param(ext)?;
+            p.param(2)?;
+            p.param(r.into())?;
+            p.param(g.into())?;
+            p.param(b.into())
+        }
+    }
+}
+
+/// Writes `;`-separated SGR parameters, starting the sequence lazily.
+pub(crate) struct Params<'a, W: ?Sized> {
+    out: &'a mut W,
+    started: bool,
+}
+
+impl<'a, W: Write + ?Sized> Params<'a, W> {
+    #[inline]
+    pub(crate) fn new(out: &'a mut W) -> Self {
+        Self {
+            out,
+            started: false,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn param(&mut self, n: u16) -> fmt::Result {
+        self.out
+            .write_str(if self.started { ";" } else { "\x1b[" })?;
+        self.started = true;
+        write!(self.out, "{n}")
+    }
+
+    /// Write a parameter (or several) that's already been formatted.
+    #[inline]
+    pub(crate) fn raw(&mut self, s: &str) -> fmt::Result {
+        self.out
+            .write_str(if self.started { ";" } else { "\x1b[" })?;
+        self.started = true;
+        self.out.write_str(s)
+    }
+
+    /// Ends the sequence, if anything was written.
+    #[inline]
+    pub(crate) fn finish(self) -> fmt::Result {
+        if self.started {
+            self.out.write_char('m')?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::{String, ToString};
+
+    fn sgr(s: Style, depth: ColorDepth) -> String {
+        let mut out = String::new();
+        s.write_to(depth, &mut out).unwrap();
+        assert_eq!(out, s.display(depth).to_string());
+        out
+    }
+
+    #[test]
+    fn test_depths() {
+        let s = Style::new()
+            .fg(Color::Rgb(0x5f, 0x87, 0xaf))
+            .bg(Color::Indexed(1))
+            .underline_color(Color::Rgb(1, 2, 3));
+        assert_eq!(
+            sgr(s, ColorDepth::TrueColor),
+            "\x1b[38;2;95;135;175;48;5;1;58;2;1;2;3m",
+        );
+        assert_eq!(sgr(s, ColorDepth::Ansi256), "\x1b[38;5;67;48;5;1;58;5;232m");
+        assert_eq!(sgr(s, ColorDepth::Ansi16), "\x1b[90;41;58;5;0m");
+        #[cfg(feature = "88color")]
+        assert_eq!(sgr(s, ColorDepth::Ansi88), "\x1b[38;5;22;48;5;1;58;5;16m");
+    }
+
+    #[test]
+    fn test_ansi16() {
+        for i in 0..8 {
+            let s = Style::new().fg(Color::Indexed(i)).bg(Color::Indexed(i + 8));
+            assert_eq!(
+                sgr(s, ColorDepth::Ansi16),
+                std::format!("\x1b[{};{}m", 30 + i, 100 + i),
+            );
+        }
+        let s = Style::new()
+            .fg(Color::Default)
+            .bg(Color::Default)
+            .underline_color(Color::Default);
+        assert_eq!(sgr(s, ColorDepth::Ansi16), "\x1b[39;49;59m");
+    }
+
+    #[test]
+    fn test_attrs() {
+        assert_eq!(sgr(Style::new(), ColorDepth::TrueColor), "");
+        let all = Attrs::BOLD
+            | Attrs::DIM
+            | Attrs::ITALIC
+            | Attrs::UNDERLINE
+            | Attrs::BLINK
+            | Attrs::REVERSE
+            | Attrs::HIDDEN
+            | Attrs::STRIKETHROUGH;
+        assert_eq!(
+            sgr(Style::new().attrs(all), ColorDepth::Ansi256),
+            "\x1b[1;2;3;4;5;7;8;9m"
+        );
+        for n in 0..16 {
+            assert_eq!(
+                Attrs::from_sgr(n).is_some(),
+                all.params().any(|p| p == n),
+                "{n}"
+            );
+        }
+    }
+}
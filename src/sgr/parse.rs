@@ -0,0 +1,352 @@
+//! Parsing SGR parameter strings into [`Event`]s.
+//!
+//! Extended colors come in several forms in the wild, all of which are
+//! handled:
+//!
+//! - `38;5;n` and `38;2;r;g;b`: the (technically incorrect, but by far most
+//!   common) semicolon-separated forms.
+//! - `38:5:n`, `38:2::r:g:b` and `38:2:cs:r:g:b`: the ITU T.416 colon forms,
+//!   with and without the (ignored) color space id.
+//! - `38:2:r:g:b`: the colon form without the color space id field at all,
+//!   which a few terminals emit.
+//!
+//! The same goes for `48` (background) and `58` (underline color).
+use super::{Attrs, Color};
+use core::fmt;
+
+/// A single change to the current style, produced by a [`Parser`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// `0` (or an empty parameter list): reset everything.
+    Reset,
+    /// Turn on an attribute, e.g. `1` for bold.
+    Set(Attrs),
+    /// Turn off attributes, e.g. `22` for both bold and dim.
+    Unset(Attrs),
+    /// `30`-`39`, `90`-`97`.
+    Fg(Color),
+    /// `40`-`49`, `100`-`107`.
+    Bg(Color),
+    /// `58`, `59`.
+    UnderlineColor(Color),
+    /// A well-formed parameter that doesn't affect colors or any of the
+    /// [`Attrs`] (such as `10`, selecting the primary font).
+    Other(u16),
+}
+
+/// Why an SGR sequence couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// The sequence wasn't a CSI sequence ending in `m`.
+    NotSgr,
+    /// A byte other than a digit, `;` or `:` was found in the parameters.
+    InvalidByte(u8),
+    /// A parameter didn't fit in a `u16`.
+    Overflow,
+    /// An extended color (`38`, `48` or `58`) was missing some components.
+    Truncated,
+    /// An extended color used a kind other than `2` (RGB) or `5` (indexed),
+    /// such as the rarely supported `3` (CMY) or `4` (CMYK).
+    UnsupportedColorKind(u16),
+    /// A color component or index was greater than 255.
+    OutOfRange(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::NotSgr => f.write_str("not an SGR sequence"),
+            Error::InvalidByte(b) => write!(f, "invalid byte in SGR parameters: {b:#04x}"),
+            Error::Overflow => f.write_str("SGR parameter too large"),
+            Error::Truncated => f.write_str("extended color is missing components"),
+            Error::UnsupportedColorKind(k) => write!(f, "unsupported extended color kind: {k}"),
+            Error::OutOfRange(v) => write!(f, "color component out of range: {v}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Parse the parameters of an SGR sequence: the part between the `\x1b[` and
+/// the final `m`.
+///
+/// ```
+/// use termpal::sgr::{parse, Color, Event};
+///
+/// let events: Vec<_> = parse(b"1;38:2::255:128:0;48;5;17").collect();
+/// assert_eq!(
+///     events,
+///     [
+///         Ok(Event::Set(termpal::sgr::Attrs::BOLD)),
+///         Ok(Event::Fg(Color::Rgb(255, 128, 0))),
+///         Ok(Event::Bg(Color::Indexed(17))),
+///     ],
+/// );
+/// ```
+#[inline]
+pub fn parse(params: &[u8]) -> Parser<'_> {
+    Parser {
+        rest: params,
+        started: false,
+        done: false,
+    }
+}
+
+/// Like [`parse`], but takes a whole sequence, including the `\x1b[` (or
+/// 8-bit CSI) and the final `m`.
+pub fn parse_sequence(seq: &[u8]) -> Result<Parser<'_>, Error> {
+    let body = seq
+        .strip_prefix(b"\x1b[")
+        .or_else(|| seq.strip_prefix(b"\x9b"))
+        .and_then(|s| s.strip_suffix(b"m"))
+        .ok_or(Error::NotSgr)?;
+    Ok(parse(body))
+}
+
+/// An iterator over the [`Event`]s in an SGR parameter string.
+///
+/// After an error, the iterator stops (returns `None`), as it's not possible
+/// to tell where the next parameter starts reliably.
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    rest: &'a [u8],
+    started: bool,
+    done: bool,
+}
+
+impl<'a> Parser<'a> {
+    /// Remove the next `;`-separated parameter (which may contain `:`
+    /// sub-parameters).
+    fn next_param(&mut self) -> Option<&'a [u8]> {
+        if self.done {
+            return None;
+        }
+        match self.rest.iter().position(|&b| b == b';') {
+            Some(i) => {
+                let p = &self.rest[..i];
+                self.rest = &self.rest[i + 1..];
+                Some(p)
+            }
+            None => {
+                let p = self.rest;
+                self.rest = &[];
+                self.done = true;
+                Some(p)
+            }
+        }
+    }
+
+    /// Take the next parameter as a number, for the semicolon forms of the
+    /// extended colors.
+    fn next_number(&mut self) -> Result<u16, Error> {
+        let p = self.next_param().ok_or(Error::Truncated)?;
+        if p.contains(&b':') {
+            return Err(Error::Truncated);
+        }
+        number(p)
+    }
+
+    fn event(&mut self, param: &[u8]) -> Result<Event, Error> {
+        let mut subs = param.split(|&b| b == b':');
+        let n = number(subs.next().unwrap_or_default())?;
+        let has_subs = param.contains(&b':');
+        let color = |c| match n {
+            38 => Event::Fg(c),
+            48 => Event::Bg(c),
+            _ => Event::UnderlineColor(c),
+        };
+        Ok(match n {
+            0 => Event::Reset,
+            // `4:n` selects an underline style, where `4:0` means none.
+            4 if has_subs => match subs.next().map(number).transpose()? {
+                Some(0) => Event::Unset(Attrs::UNDERLINE),
+                _ => Event::Set(Attrs::UNDERLINE),
+            },
+            1..=9 => Attrs::from_sgr(n).map_or(Event::Other(n), Event::Set),
+            22 => Event::Unset(Attrs::BOLD | Attrs::DIM),
+            23..=29 => Attrs::from_sgr(n - 20).map_or(Event::Other(n), Event::Unset),
+            30..=37 => Event::Fg(Color::Indexed((n - 30) as u8)),
+            90..=97 => Event::Fg(Color::Indexed((n - 90 + 8) as u8)),
+            40..=47 => Event::Bg(Color::Indexed((n - 40) as u8)),
+            100..=107 => Event::Bg(Color::Indexed((n - 100 + 8) as u8)),
+            39 => Event::Fg(Color::Default),
+            49 => Event::Bg(Color::Default),
+            59 => Event::UnderlineColor(Color::Default),
+            38 | 48 | 58 if has_subs => color(colon_color(subs)?),
+            38 | 48 | 58 => {
+                let c = match self.next_number()? {
+                    5 => Color::Indexed(component(self.next_number()?)?),
+                    2 => {
+                        let r = component(self.next_number()?)?;
+                        let g = component(self.next_number()?)?;
+                        let b = component(self.next_number()?)?;
+                        Color::Rgb(r, g, b)
+                    }
+                    k => return Err(Error::UnsupportedColorKind(k)),
+                };
+                color(c)
+            }
+            n => Event::Other(n),
+        })
+    }
+
+    /// Like `next`, but also returns the parameter(s) the event was parsed
+    /// from (without the trailing `;`), so that callers can leave the events
+    /// they don't care about exactly as they were.
+    pub(crate) fn next_with_raw(&mut self) -> Option<(Result<Event, Error>, &'a [u8])> {
+        let before = self.rest;
+        let ev = self.next()?;
+        let raw = &before[..before.len() - self.rest.len()];
+        Some((ev, raw.strip_suffix(b";").unwrap_or(raw)))
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started && self.rest.is_empty() {
+            // `\x1b[m` is the same as `\x1b[0m`.
+            self.started = true;
+            self.done = true;
+            return Some(Ok(Event::Reset));
+        }
+        self.started = true;
+        let p = self.next_param()?;
+        let r = self.event(p);
+        if r.is_err() {
+            self.done = true;
+        }
+        Some(r)
+    }
+}
+
+impl core::iter::FusedIterator for Parser<'_> {}
+
+/// Parse the sub-parameters after the `38:`.
+fn colon_color<'a>(subs: impl Iterator<Item = &'a [u8]>) -> Result<Color, Error> {
+    let mut v = [0u16; 8];
+    let mut len = 0;
+    // An empty field right after the `2` can only be a color space id.
+    let mut has_cs = false;
+    for s in subs {
+        // Anything past the ITU tolerance fields is ignored.
+        if len < v.len() {
+            v[len] = number(s)?;
+        }
+        has_cs |= len == 1 && s.is_empty();
+        len += 1;
+    }
+    match (v[0], len) {
+        (_, 0) => Err(Error::Truncated),
+        (5, 2..) => Ok(Color::Indexed(component(v[1])?)),
+        // `38:2:r:g:b`, without a color space id.
+        (2, 4) if !has_cs => Ok(Color::Rgb(
+            component(v[1])?,
+            component(v[2])?,
+            component(v[3])?,
+        )),
+        // `38:2:cs:r:g:b`, where `cs` is usually empty.
+        (2, 5..) => Ok(Color::Rgb(
+            component(v[2])?,
+            component(v[3])?,
+            component(v[4])?,
+        )),
+        (2 | 5, _) => Err(Error::Truncated),
+        (k, _) => Err(Error::UnsupportedColorKind(k)),
+    }
+}
+
+/// Parse a decimal parameter. Empty parameters are 0.
+fn number(s: &[u8]) -> Result<u16, Error> {
+    let mut n: u16 = 0;
+    for &b in s {
+        if !b.is_ascii_digit() {
+            return Err(Error::InvalidByte(b));
+        }
+        n = n
+            .checked_mul(10)
+            .and_then(|n| n.checked_add((b - b'0') as u16))
+            .ok_or(Error::Overflow)?;
+    }
+    Ok(n)
+}
+
+#[inline]
+fn component(n: u16) -> Result<u8, Error> {
+    u8::try_from(n).map_err(|_| Error::OutOfRange(n))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec::Vec;
+
+    fn events(s: &str) -> Vec<Result<Event, Error>> {
+        parse(s.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_color_forms() {
+        let rgb = Ok(Event::Fg(Color::Rgb(1, 2, 3)));
+        assert_eq!(events("38;2;1;2;3"), [rgb]);
+        assert_eq!(events("38:2::1:2:3"), [rgb]);
+        assert_eq!(events("38:2:0:1:2:3"), [rgb]);
+        assert_eq!(events("38:2:1:2:3"), [rgb]);
+        assert_eq!(events("38:2::1:2:3:0:0:0:0:0"), [rgb]);
+        let idx = Ok(Event::Bg(Color::Indexed(200)));
+        assert_eq!(events("48;5;200"), [idx]);
+        assert_eq!(events("48:5:200"), [idx]);
+        assert_eq!(
+            events("58:2::10:20:30;59"),
+            [
+                Ok(Event::UnderlineColor(Color::Rgb(10, 20, 30))),
+                Ok(Event::UnderlineColor(Color::Default)),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_mixed() {
+        assert_eq!(events(""), [Ok(Event::Reset)]);
+        assert_eq!(
+            events("0;1;;31;38;5;9;4:3;24;97;49;10"),
+            [
+                Ok(Event::Reset),
+                Ok(Event::Set(Attrs::BOLD)),
+                Ok(Event::Reset),
+                Ok(Event::Fg(Color::Indexed(1))),
+                Ok(Event::Fg(Color::Indexed(9))),
+                Ok(Event::Set(Attrs::UNDERLINE)),
+                Ok(Event::Unset(Attrs::UNDERLINE)),
+                Ok(Event::Fg(Color::Indexed(15))),
+                Ok(Event::Bg(Color::Default)),
+                Ok(Event::Other(10)),
+            ],
+        );
+        assert_eq!(
+            parse_sequence(b"\x1b[22;27m").unwrap().collect::<Vec<_>>(),
+            [
+                Ok(Event::Unset(Attrs::BOLD | Attrs::DIM)),
+                Ok(Event::Unset(Attrs::REVERSE)),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(events("38;5"), [Err(Error::Truncated)]);
+        assert_eq!(events("38;2;1;2"), [Err(Error::Truncated)]);
+        assert_eq!(events("38:2::1:2"), [Err(Error::Truncated)]);
+        assert_eq!(events("38;5:1"), [Err(Error::Truncated)]);
+        assert_eq!(
+            events("1;38;5;256;1"),
+            [Ok(Event::Set(Attrs::BOLD)), Err(Error::OutOfRange(256))]
+        );
+        assert_eq!(events("38;3;1;2;3"), [Err(Error::UnsupportedColorKind(3))]);
+        assert_eq!(events("99999"), [Err(Error::Overflow)]);
+        assert_eq!(events("1x"), [Err(Error::InvalidByte(b'x'))]);
+        assert_eq!(parse_sequence(b"\x1b[1H").err(), Some(Error::NotSgr));
+    }
+}
@@ -0,0 +1,347 @@
+//! Encoding images as [Sixel](https://vt100.net/docs/vt3xx-gp/chapter14.html)
+//! graphics.
+//!
+//! Sixel images are drawn using a set of (at most 256, in practice) color
+//! registers, which means every pixel has to be quantized to one of those
+//! first. That's just the nearest color search this crate already does, so
+//! the [`Encoder`] can either:
+//!
+//! - Use the xterm 256-color table, quantizing with
+//!   [`nearest_ansi256`](crate::nearest_ansi256). This is fast (the search is
+//!   cached and SIMD-accelerated), and looks fine for most images.
+//!
+//! - Build a palette for the image using a [`palette::Builder`], and then
+//!   search that. This is slower, but better for images with a lot of subtle
+//!   gradients, or that are mostly one hue.
+//!
+//! - Use a [`Palette`] you've built yourself.
+//!
+//! Only the registers that are actually used are defined, and runs of the same
+//! sixel are run-length encoded.
+use crate::palette::{self, Palette};
+use crate::render::Image;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// How an [`Encoder`] chooses the color registers.
+#[derive(Clone, Copy, Debug)]
+pub enum Colors<'a> {
+    /// Use the xterm 256-color table (entries `16..=255`, as the named colors
+    /// are never chosen).
+    Ansi256,
+    /// Generate a palette of at most this many colors (clamped to `1..=256`)
+    /// for each image.
+    Adaptive(usize),
+    /// Use the given palette.
+    Fixed(&'a Palette),
+}
+
+/// Writes [`Image`]s as Sixel DCS sequences.
+///
+/// ```
+/// use termpal::render::Image;
+/// use termpal::sixel::{Colors, Encoder};
+///
+/// let pixels = [0xff, 0x00, 0x00, 0x00, 0x00, 0xff];
+/// let image = Image::from_rgb8(&pixels, 2, 1);
+/// let mut out = String::new();
+/// Encoder::new(Colors::Ansi256).encode(&image, &mut out).unwrap();
+/// assert_eq!(
+///     out,
+///     "\x1bP0;1;0q\"1;1;2;1#21;2;0;0;100#196;2;100;0;0#21?@$#196@\x1b\\",
+/// );
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Encoder<'a> {
+    colors: Colors<'a>,
+}
+
+impl<'a> Encoder<'a> {
+    #[inline]
+    pub const fn new(colors: Colors<'a>) -> Self {
+        Self { colors }
+    }
+
+    /// Write `image` to `out`, as a complete DCS sequence.
+    pub fn encode<W: Write + ?Sized>(&self, image: &Image<'_>, out: &mut W) -> fmt::Result {
+        let (w, h) = (image.width(), image.height());
+        let adaptive;
+        let palette = match self.colors {
+            Colors::Ansi256 => None,
+            Colors::Adaptive(n) => {
+                adaptive = palette::Builder::new(n).build(image.pixels());
+                Some(&adaptive)
+            }
+            Colors::Fixed(p) => Some(p),
+        };
+        let mut indices = Vec::with_capacity(w * h);
+        let mut last = None;
+        for (r, g, b) in image.pixels() {
+            // Images tend to have runs of the same color, and the palette
+            // search is uncached, so this is worth doing.
+            let i = match last {
+                Some((c, i)) if c == (r, g, b) => i,
+                _ => match palette {
+                    None => crate::nearest_ansi256(r, g, b),
+                    Some(p) => p.nearest(r, g, b),
+                },
+            };
+            last = Some(((r, g, b), i));
+            indices.push(i);
+        }
+        let register = |i: u8| -> (u8, u8, u8) {
+            match palette {
+                None => crate::ansi256_to_rgb(i),
+                Some(p) => p.get(i).unwrap_or((0, 0, 0)),
+            }
+        };
+
+        // P2 = 1 leaves pixels that aren't drawn alone, and the raster
+        // attributes give square pixels.
+        write!(out, "\x1bP0;1;0q\"1;1;{w};{h}")?;
+        let mut used = [false; 256];
+        for &i in &indices {
+            used[i as usize] = true;
+        }
+        for i in (0..=255u8).filter(|&i| used[i as usize]) {
+            let (r, g, b) = register(i);
+            let pct = |c: u8| (c as u32 * 100 + 127) / 255;
+            write!(out, "#{i};2;{};{};{}", pct(r), pct(g), pct(b))?;
+        }
+
+        let bands = h.div_ceil(6);
+        for band in 0..bands {
+            let rows = &indices[band * 6 * w..((band + 1) * 6).min(h) * w];
+            let mut in_band = [false; 256];
+            for &i in rows {
+                in_band[i as usize] = true;
+            }
+            let mut first = true;
+            for color in (0..=255u8).filter(|&i| in_band[i as usize]) {
+                if !first {
+                    // Carriage return, so the next color draws over the same
+                    // band.
+                    out.write_char('$')?;
+                }
+                first = false;
+                write!(out, "#{color}")?;
+                let mut run = Run::default();
+                for x in 0..w {
+                    let bits = rows
+                        .chunks_exact(w)
+                        .enumerate()
+                        .filter(|(_, row)| row[x] == color)
+                        .fold(0u8, |bits, (y, _)| bits | (1 << y));
+                    run.push(out, (b'?' + bits) as char)?;
+                }
+                // Trailing empty sixels don't need to be written.
+                if run.ch != '?' {
+                    run.flush(out)?;
+                }
+            }
+            if band + 1 != bands {
+                out.write_char('-')?;
+            }
+        }
+        out.write_str("\x1b\\")
+    }
+}
+
+/// Run-length encoder for sixel characters.
+#[derive(Default)]
+struct Run {
+    ch: char,
+    count: usize,
+}
+
+impl Run {
+    fn push<W: Write + ?Sized>(&mut self, out: &mut W, ch: char) -> fmt::Result {
+        if self.count != 0 && ch != self.ch {
+            self.flush(out)?;
+        }
+        self.ch = ch;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn flush<W: Write + ?Sized>(&mut self, out: &mut W) -> fmt::Result {
+        // `!3?` is no shorter than `???`.
+        if self.count > 3 {
+            write!(out, "!{}{}", self.count, self.ch)?;
+        } else {
+            for _ in 0..self.count {
+                out.write_char(self.ch)?;
+            }
+        }
+        self.count = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::String;
+    use std::vec;
+
+    /// The result of decoding a sixel image: the register each pixel was
+    /// drawn with, and the colors of the registers (as percentages).
+    struct Decoded {
+        width: usize,
+        height: usize,
+        pixels: Vec<Option<u8>>,
+        registers: [Option<(u32, u32, u32)>; 256],
+    }
+
+    fn decode(s: &str) -> Decoded {
+        let s = s.strip_prefix("\x1bP").expect("no DCS");
+        let s = s.strip_suffix("\x1b\\").expect("no ST");
+        let (_, body) = s.split_once('q').expect("no `q`");
+        let mut it = body.chars().peekable();
+        let num = |it: &mut core::iter::Peekable<core::str::Chars<'_>>| -> usize {
+            let mut n = 0;
+            while let Some(d) = it.peek().and_then(|c| c.to_digit(10)) {
+                n = n * 10 + d as usize;
+                it.next();
+            }
+            n
+        };
+        assert_eq!(it.next(), Some('"'));
+        let mut raster = [0; 4];
+        for (i, v) in raster.iter_mut().enumerate() {
+            if i != 0 {
+                assert_eq!(it.next(), Some(';'));
+            }
+            *v = num(&mut it);
+        }
+        let [_, _, width, height] = raster;
+        let mut d = Decoded {
+            width,
+            height,
+            pixels: vec![None; width * height],
+            registers: [None; 256],
+        };
+        let (mut x, mut y, mut color) = (0, 0, 0u8);
+        while let Some(c) = it.next() {
+            let mut count = 1;
+            let c = match c {
+                '#' => {
+                    color = num(&mut it) as u8;
+                    if it.peek() == Some(&';') {
+                        it.next();
+                        assert_eq!(num(&mut it), 2, "only RGB is supported");
+                        let mut rgb = [0; 3];
+                        for v in &mut rgb {
+                            assert_eq!(it.next(), Some(';'));
+                            *v = num(&mut it) as u32;
+                        }
+                        d.registers[color as usize] = Some((rgb[0], rgb[1], rgb[2]));
+                    }
+                    continue;
+                }
+                '$' => {
+                    x = 0;
+                    continue;
+                }
+                '-' => {
+                    x = 0;
+                    y += 6;
+                    continue;
+                }
+                '!' => {
+                    count = num(&mut it);
+                    it.next().unwrap()
+                }
+                c => c,
+            };
+            assert!(('?'..='~').contains(&c), "bad sixel {c:?}");
+            let bits = c as u8 - b'?';
+            for _ in 0..count {
+                for dy in 0..6 {
+                    if bits & (1 << dy) != 0 {
+                        assert!(x < width && y + dy < height, "out of bounds");
+                        d.pixels[(y + dy) * width + x] = Some(color);
+                    }
+                }
+                x += 1;
+            }
+        }
+        d
+    }
+
+    fn test_image(w: usize, h: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                let c = match (x / 4 + y / 3) % 4 {
+                    0 => [200, 30, 30],
+                    1 => [30, 160, 40],
+                    2 => [30, 30, 30],
+                    _ => [240, 240, 100],
+                };
+                data.extend_from_slice(&c);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_roundtrip_ansi256() {
+        let (w, h) = (23, 13);
+        let data = test_image(w, h);
+        let img = Image::from_rgb8(&data, w, h);
+        let mut s = String::new();
+        Encoder::new(Colors::Ansi256).encode(&img, &mut s).unwrap();
+        assert!(s.contains('!'), "expected some RLE: {s:?}");
+        let d = decode(&s);
+        assert_eq!((d.width, d.height), (w, h));
+        for (i, (r, g, b)) in img.pixels().enumerate() {
+            let want = crate::nearest_ansi256(r, g, b);
+            assert_eq!(d.pixels[i], Some(want), "pixel {i}");
+            let (er, eg, eb) = crate::ansi256_to_rgb(want);
+            let (pr, pg, pb) = d.registers[want as usize].unwrap();
+            assert_eq!(
+                (pr, pg, pb),
+                (
+                    (er as u32 * 100 + 127) / 255,
+                    (eg as u32 * 100 + 127) / 255,
+                    (eb as u32 * 100 + 127) / 255
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_adaptive() {
+        let (w, h) = (16, 7);
+        let data = test_image(w, h);
+        let img = Image::from_rgb8(&data, w, h);
+        let mut s = String::new();
+        Encoder::new(Colors::Adaptive(4))
+            .encode(&img, &mut s)
+            .unwrap();
+        let d = decode(&s);
+        assert_eq!(d.registers.iter().flatten().count(), 4);
+        for (i, (r, g, b)) in img.pixels().enumerate() {
+            let reg = d.pixels[i].expect("undrawn pixel");
+            let (pr, pg, pb) = d.registers[reg as usize].unwrap();
+            let pct = |c: u8| c as u32 * 100 / 255;
+            assert!(
+                pr.abs_diff(pct(r)) <= 1 && pg.abs_diff(pct(g)) <= 1 && pb.abs_diff(pct(b)) <= 1,
+                "pixel {i}: {:?} vs {:?}",
+                (r, g, b),
+                (pr, pg, pb),
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let img = Image::from_rgb8(&[], 0, 0);
+        let mut s = String::new();
+        Encoder::new(Colors::Adaptive(16))
+            .encode(&img, &mut s)
+            .unwrap();
+        assert_eq!(s, "\x1bP0;1;0q\"1;1;0;0\x1b\\");
+    }
+}
@@ -0,0 +1,134 @@
+//! Quantizing colors that change over time, without flicker.
+//!
+//! When a color is animated (a fade, a pulsing spinner, a progress bar that
+//! shifts from red to green), consecutive frames will often land either side
+//! of the boundary between two palette entries, and independent calls to
+//! [`nearest_ansi256`](crate::nearest_ansi256) flip back and forth between
+//! them. A [`StableQuantizer`] remembers what it picked last time, and only
+//! moves on once something else is better by a noticeable margin.
+use crate::imp::oklab::{sqrt, OkLab};
+use crate::imp::tab::LAB_PALETTE_ANSI256;
+
+/// The default [`StableQuantizer::margin`], in Oklab units.
+///
+/// This is small enough that a switch is never more than barely visibly
+/// late, but large enough to cover the noise from most fades.
+pub const DEFAULT_MARGIN: f32 = 0.01;
+
+/// Maps one animated color to 256-color palette entries, sticking with the
+/// previous entry until another is better by at least a margin.
+///
+/// Use one of these per channel (e.g. one for a spinner's foreground, and
+/// another for its background), as each only remembers a single entry.
+///
+/// ```
+/// use termpal::stable::StableQuantizer;
+///
+/// let mut q = StableQuantizer::new();
+/// for step in 0..=255 {
+///     let index = q.nearest_ansi256(step, 0x40, 0xff - step);
+///     print!("\x1b[38;5;{index}m█");
+/// }
+/// println!("\x1b[0m");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StableQuantizer {
+    margin: f32,
+    current: Option<u8>,
+}
+
+impl StableQuantizer {
+    /// A quantizer with [`DEFAULT_MARGIN`], that hasn't picked anything yet.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            margin: DEFAULT_MARGIN,
+            current: None,
+        }
+    }
+
+    /// Set how much closer (as an Oklab distance) another entry needs to be
+    /// before switching to it. With `0.0`, the current entry is only kept
+    /// while it's at least as close as the nearest one.
+    #[inline]
+    pub const fn margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// The entry that was picked last, if any.
+    #[inline]
+    pub const fn current(&self) -> Option<u8> {
+        self.current
+    }
+
+    /// Forget the current entry, so that the next call picks the nearest,
+    /// e.g. when an animation restarts.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Returns the entry to use for `(r, g, b)`: the same as last time if it's
+    /// still within the margin of the nearest, and otherwise the nearest.
+    pub fn nearest_ansi256(&mut self, r: u8, g: u8, b: u8) -> u8 {
+        let best = crate::nearest_ansi256(r, g, b);
+        if let Some(current) = self.current.filter(|&c| c != best) {
+            let lab = OkLab::from_srgb8(r, g, b);
+            let to_current = distance(&lab, current);
+            let to_best = distance(&lab, best);
+            if to_current <= to_best + self.margin {
+                return current;
+            }
+        }
+        self.current = Some(best);
+        best
+    }
+}
+
+impl Default for StableQuantizer {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn distance(lab: &OkLab, index: u8) -> f32 {
+    let e = &LAB_PALETTE_ANSI256[index as usize - 16];
+    sqrt(lab.dist_sq(e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nearest_ansi256;
+
+    #[test]
+    fn test_hysteresis() {
+        // Jitter around the boundary between two neighbors.
+        let frames = [(0x9a, 0, 0), (0x9b, 0, 0), (0x9a, 0, 0), (0x9c, 0, 0)];
+        let plain: std::vec::Vec<u8> = frames
+            .iter()
+            .map(|&(r, g, b)| nearest_ansi256(r, g, b))
+            .collect();
+        assert_eq!(plain, [88, 124, 88, 124]);
+
+        let mut q = StableQuantizer::new();
+        assert_eq!(q.current(), None);
+        for &(r, g, b) in &frames {
+            assert_eq!(q.nearest_ansi256(r, g, b), 88);
+        }
+        // Far enough along, it does switch.
+        assert_eq!(q.nearest_ansi256(0xaf, 0, 0), 124);
+        assert_eq!(q.current(), Some(124));
+        q.reset();
+        assert_eq!(q.nearest_ansi256(0x9c, 0, 0), 124);
+
+        // With no margin, this follows the plain search.
+        let mut q = StableQuantizer::new().margin(0.0);
+        for (&(r, g, b), &want) in frames.iter().zip(&plain) {
+            assert_eq!(q.nearest_ansi256(r, g, b), want);
+        }
+    }
+}
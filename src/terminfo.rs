@@ -0,0 +1,658 @@
+//! Reading the color-related capabilities from compiled terminfo entries.
+//!
+//! This handles both the legacy format (16-bit numbers), and the one ncurses 6
+//! uses for entries with large numbers (32-bit numbers, e.g. `colors#0x1000000`
+//! in `xterm-direct`), as well as the extended section that holds
+//! user-defined capabilities like `RGB` and `Tc`. Nothing is copied or
+//! allocated: a [`Terminfo`] borrows from the file's contents, which you'll
+//! need to read yourself (or see [`detect`](crate::detect), which does the
+//! searching for you).
+//!
+//! The `setaf`/`setab` capabilities are [`Template`]s, which can be expanded
+//! to get the exact sequence the terminal expects for a color, rather than
+//! assuming it uses the xterm ones.
+//!
+//! ```
+//! use termpal::terminfo::Terminfo;
+//!
+//! let data = include_bytes!("../testdata/terminfo/xterm-256color");
+//! let info = Terminfo::parse(data).unwrap();
+//! assert_eq!(info.colors(), Some(256));
+//! assert!(!info.truecolor());
+//!
+//! let mut seq = String::new();
+//! info.setaf().unwrap().expand(&[196], &mut seq).unwrap();
+//! assert_eq!(seq, "\x1b[38;5;196m");
+//! ```
+//!
+//! See `term(5)` for the format.
+use core::fmt::{self, Write};
+
+/// The legacy format's magic number, with 16-bit numbers.
+const MAGIC_16: u16 = 0o432;
+/// The ncurses 6 format's magic number, with 32-bit numbers.
+const MAGIC_32: u16 = 0o1036;
+
+// Indices of the capabilities we care about, in the standard order.
+const COLORS: usize = 13;
+const PAIRS: usize = 14;
+const SETAF: usize = 359;
+const SETAB: usize = 360;
+
+/// Why a terminfo entry couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// The magic number wasn't one of the known ones, so this probably isn't
+    /// a compiled terminfo entry (or it's in the hashed database format, which
+    /// isn't supported).
+    BadMagic,
+    /// The data ended before a section did.
+    Truncated,
+    /// A count or size in the header was negative.
+    BadHeader,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a compiled terminfo entry",
+            Error::Truncated => "terminfo entry is truncated",
+            Error::BadHeader => "terminfo entry has an invalid header",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// A parsed terminfo entry.
+#[derive(Clone, Copy, Debug)]
+pub struct Terminfo<'a> {
+    names: &'a [u8],
+    numbers: Numbers<'a>,
+    strings: Strings<'a>,
+    ext: Option<Extended<'a>>,
+}
+
+/// The user-defined capabilities.
+#[derive(Clone, Copy, Debug)]
+struct Extended<'a> {
+    bools: &'a [u8],
+    numbers: Numbers<'a>,
+    /// The string values, and then the names, which are stored in the same
+    /// table.
+    strings: Strings<'a>,
+    names: Strings<'a>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Numbers<'a> {
+    data: &'a [u8],
+    wide: bool,
+}
+
+impl Numbers<'_> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.data.len() / if self.wide { 4 } else { 2 }
+    }
+
+    /// The value of number `i`, or `None` if it's absent or cancelled.
+    fn get(&self, i: usize) -> Option<i32> {
+        let v = if self.wide {
+            let b = self.data.get(i * 4..i * 4 + 4)?;
+            i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            let b = self.data.get(i * 2..i * 2 + 2)?;
+            i16::from_le_bytes([b[0], b[1]]).into()
+        };
+        (v >= 0).then_some(v)
+    }
+}
+
+/// A table of offsets into a table of NUL-terminated strings.
+#[derive(Clone, Copy, Debug)]
+struct Strings<'a> {
+    offsets: &'a [u8],
+    table: &'a [u8],
+}
+
+impl<'a> Strings<'a> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.offsets.len() / 2
+    }
+
+    /// The offset of string `i`, or `None` if it's absent or cancelled.
+    #[inline]
+    fn offset(&self, i: usize) -> Option<usize> {
+        let b = self.offsets.get(i * 2..i * 2 + 2)?;
+        usize::try_from(i16::from_le_bytes([b[0], b[1]])).ok()
+    }
+
+    fn get(&self, i: usize) -> Option<&'a [u8]> {
+        let s = self.table.get(self.offset(i)?..)?;
+        Some(&s[..s.iter().position(|&b| b == 0)?])
+    }
+}
+
+/// Splits sections off the front of the data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let s = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(Error::Truncated)?;
+        self.pos += n;
+        Ok(s)
+    }
+
+    /// Read `N` little-endian `i16` counts, which must not be negative.
+    fn counts<const N: usize>(&mut self) -> Result<[usize; N], Error> {
+        let b = self.take(N * 2)?;
+        let mut out = [0; N];
+        for (i, v) in out.iter_mut().enumerate() {
+            let n = i16::from_le_bytes([b[i * 2], b[i * 2 + 1]]);
+            *v = usize::try_from(n).map_err(|_| Error::BadHeader)?;
+        }
+        Ok(out)
+    }
+
+    /// Skip to an even offset, as the sections after the booleans are aligned.
+    #[inline]
+    fn align(&mut self) {
+        self.pos += self.pos & 1;
+    }
+}
+
+impl<'a> Terminfo<'a> {
+    /// Parse the contents of a compiled terminfo file.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let mut r = Reader { data, pos: 0 };
+        let magic = r.take(2)?;
+        let wide = match u16::from_le_bytes([magic[0], magic[1]]) {
+            MAGIC_16 => false,
+            MAGIC_32 => true,
+            _ => return Err(Error::BadMagic),
+        };
+        let num_size = if wide { 4 } else { 2 };
+        let [names, bools, nums, strs, table] = r.counts()?;
+        let names = r.take(names)?;
+        r.take(bools)?;
+        r.align();
+        let numbers = Numbers {
+            data: r.take(nums * num_size)?,
+            wide,
+        };
+        let strings = Strings {
+            offsets: r.take(strs * 2)?,
+            table: r.take(table)?,
+        };
+
+        // The extended section is optional, and older parsers ignore anything
+        // they don't understand, so we do too.
+        r.align();
+        let ext = parse_extended(&mut r, wide).ok();
+        Ok(Self {
+            names: names.strip_suffix(b"\0").unwrap_or(names),
+            numbers,
+            strings,
+            ext,
+        })
+    }
+
+    /// The terminal's names, e.g. `xterm-256color`, with the last one usually
+    /// being a longer description.
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + 'a {
+        core::str::from_utf8(self.names)
+            .unwrap_or_default()
+            .split('|')
+    }
+
+    /// The `colors` capability: the number of colors the terminal supports.
+    #[inline]
+    pub fn colors(&self) -> Option<i32> {
+        self.numbers.get(COLORS)
+    }
+
+    /// The `pairs` capability: the number of color pairs curses can use.
+    #[inline]
+    pub fn pairs(&self) -> Option<i32> {
+        self.numbers.get(PAIRS)
+    }
+
+    /// The `setaf` capability, which sets the foreground color.
+    #[inline]
+    pub fn setaf(&self) -> Option<Template<'a>> {
+        self.strings.get(SETAF).map(Template)
+    }
+
+    /// The `setab` capability, which sets the background color.
+    #[inline]
+    pub fn setab(&self) -> Option<Template<'a>> {
+        self.strings.get(SETAB).map(Template)
+    }
+
+    /// Whether the terminal supports direct (24-bit) color, via the `RGB`
+    /// extended capability ncurses uses, or the `Tc` one from tmux.
+    #[inline]
+    pub fn truecolor(&self) -> bool {
+        self.has_extended("RGB") || self.has_extended("Tc")
+    }
+
+    /// Whether the extended (user-defined) capability `name` is present (and
+    /// not cancelled), whatever its type.
+    pub fn has_extended(&self, name: &str) -> bool {
+        let Some(ext) = self.ext else {
+            return false;
+        };
+        let (bools, nums) = (ext.bools.len(), ext.numbers.len());
+        (0..ext.names.len())
+            .filter(|&i| ext.names.get(i) == Some(name.as_bytes()))
+            .any(|i| match i {
+                i if i < bools => ext.bools[i] == 1,
+                i if i < bools + nums => ext.numbers.get(i - bools).is_some(),
+                i => ext.strings.offset(i - bools - nums).is_some(),
+            })
+    }
+}
+
+fn parse_extended<'a>(r: &mut Reader<'a>, wide: bool) -> Result<Extended<'a>, Error> {
+    let [bools, nums, strs, _items, table] = r.counts()?;
+    let bools_data = r.take(bools)?;
+    r.align();
+    let numbers = Numbers {
+        data: r.take(nums * if wide { 4 } else { 2 })?,
+        wide,
+    };
+    let values = r.take(strs * 2)?;
+    let names = r.take((bools + nums + strs) * 2)?;
+    let table = r.take(table)?;
+    let strings = Strings {
+        offsets: values,
+        table,
+    };
+    // The names come straight after the last string value.
+    let names_start = (0..strs)
+        .filter_map(|i| Some(strings.offset(i)? + strings.get(i)?.len() + 1))
+        .max()
+        .unwrap_or(0);
+    Ok(Extended {
+        bools: bools_data,
+        numbers,
+        strings,
+        names: Strings {
+            offsets: names,
+            table: table.get(names_start..).unwrap_or_default(),
+        },
+    })
+}
+
+/// A parameterized string capability, such as `setaf`.
+///
+/// These use a little stack-based language (see `terminfo(5)`), e.g. the
+/// `xterm-256color` `setaf` is
+/// `\E[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Template<'a>(&'a [u8]);
+
+impl fmt::Debug for Template<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Template(\"{}\")", self.0.escape_ascii())
+    }
+}
+
+impl<'a> Template<'a> {
+    /// The template, as it appears in the entry.
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Expand the template with `params` (`%p1` is `params[0]`, and so on,
+    /// with missing ones being `0`), writing the result to `out`.
+    ///
+    /// Like curses, this is lenient about malformed templates: unknown
+    /// operators are ignored, and popping an empty stack gives `0`. String
+    /// parameters aren't supported, as none of the color capabilities use
+    /// them.
+    pub fn expand<W: Write + ?Sized>(&self, params: &[i32], out: &mut W) -> fmt::Result {
+        let mut p = [0i32; 9];
+        for (p, &v) in p.iter_mut().zip(params) {
+            *p = v;
+        }
+        let mut stack = Stack::default();
+        let mut vars = [0i32; 52];
+        let s = self.0;
+        let mut i = 0;
+        while i < s.len() {
+            let Some(rel) = s[i..].iter().position(|&b| b == b'%') else {
+                write_bytes(out, &s[i..])?;
+                break;
+            };
+            write_bytes(out, &s[i..i + rel])?;
+            i += rel + 1;
+            let Some(&op) = s.get(i) else {
+                break;
+            };
+            i += 1;
+            match op {
+                b'%' => out.write_char('%')?,
+                b'c' => out.write_char(char::from(stack.pop() as u8))?,
+                b'p' => {
+                    let n = s.get(i).map_or(0, |d| d.wrapping_sub(b'1'));
+                    i += 1;
+                    stack.push(p.get(n as usize).copied().unwrap_or(0));
+                }
+                b'P' | b'g' => {
+                    let var = s.get(i).and_then(|&c| match c {
+                        b'a'..=b'z' => Some((c - b'a') as usize),
+                        b'A'..=b'Z' => Some((c - b'A') as usize + 26),
+                        _ => None,
+                    });
+                    i += 1;
+                    if let Some(v) = var {
+                        if op == b'P' {
+                            vars[v] = stack.pop();
+                        } else {
+                            stack.push(vars[v]);
+                        }
+                    }
+                }
+                b'\'' => {
+                    stack.push(s.get(i).copied().unwrap_or(0).into());
+                    // Skip the character and the closing quote.
+                    i += 2;
+                }
+                b'{' => {
+                    let len = s[i..]
+                        .iter()
+                        .position(|&b| b == b'}')
+                        .unwrap_or(s.len() - i);
+                    let n = s[i..i + len].iter().fold(0i32, |n, &d| {
+                        n.wrapping_mul(10)
+                            .wrapping_add((d.wrapping_sub(b'0')) as i32)
+                    });
+                    stack.push(n);
+                    i += len + 1;
+                }
+                b'l' => {
+                    // `strlen` of a string parameter, which we don't have.
+                    stack.pop();
+                    stack.push(0);
+                }
+                b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'<' | b'>'
+                | b'A' | b'O' => {
+                    let b = stack.pop();
+                    let a = stack.pop();
+                    stack.push(match op {
+                        b'+' => a.wrapping_add(b),
+                        b'-' => a.wrapping_sub(b),
+                        b'*' => a.wrapping_mul(b),
+                        b'/' => a.checked_div(b).unwrap_or(0),
+                        b'm' => a.checked_rem(b).unwrap_or(0),
+                        b'&' => a & b,
+                        b'|' => a | b,
+                        b'^' => a ^ b,
+                        b'=' => (a == b).into(),
+                        b'<' => (a < b).into(),
+                        b'>' => (a > b).into(),
+                        b'A' => (a != 0 && b != 0).into(),
+                        _ => (a != 0 || b != 0).into(),
+                    });
+                }
+                b'!' => {
+                    let a = stack.pop();
+                    stack.push((a == 0).into());
+                }
+                b'~' => {
+                    let a = stack.pop();
+                    stack.push(!a);
+                }
+                b'i' => {
+                    p[0] = p[0].wrapping_add(1);
+                    p[1] = p[1].wrapping_add(1);
+                }
+                b'?' | b';' => {}
+                b't' => {
+                    if stack.pop() == 0 {
+                        i = skip_branch(s, i, true);
+                    }
+                }
+                b'e' => {
+                    // Only reached after taking the `%t` branch.
+                    i = skip_branch(s, i, false);
+                }
+                _ => {
+                    // A printf-style conversion, like `%d` or `%:-3x`.
+                    let (spec, len) = Spec::parse(&s[i - 1..]);
+                    i += len - 1;
+                    spec.write(stack.pop(), out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Skip past the end of the current branch of a conditional, starting after
+/// the `%t` (if `to_else`) or `%e`. Returns the position after the `%e` or
+/// `%;` that ends it.
+fn skip_branch(s: &[u8], mut i: usize, to_else: bool) -> usize {
+    let mut depth = 0;
+    while i + 1 < s.len() {
+        if s[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        match s[i + 1] {
+            b'?' => depth += 1,
+            b';' if depth == 0 => return i + 2,
+            b';' => depth -= 1,
+            b'e' if depth == 0 && to_else => return i + 2,
+            _ => {}
+        }
+        i += 2;
+    }
+    s.len()
+}
+
+fn write_bytes<W: Write + ?Sized>(out: &mut W, b: &[u8]) -> fmt::Result {
+    match core::str::from_utf8(b) {
+        Ok(s) => out.write_str(s),
+        // Not really expected, so do the simple thing.
+        Err(_) => b.iter().try_for_each(|&b| out.write_char(char::from(b))),
+    }
+}
+
+#[derive(Default)]
+struct Stack {
+    data: [i32; 16],
+    len: usize,
+}
+
+impl Stack {
+    #[inline]
+    fn push(&mut self, v: i32) {
+        if self.len < self.data.len() {
+            self.data[self.len] = v;
+            self.len += 1;
+        }
+    }
+
+    #[inline]
+    fn pop(&mut self) -> i32 {
+        if self.len == 0 {
+            return 0;
+        }
+        self.len -= 1;
+        self.data[self.len]
+    }
+}
+
+/// Widths are clamped to this, since they'd otherwise be up to whoever wrote
+/// the terminfo entry, and `core::fmt` panics on huge ones.
+const MAX_WIDTH: usize = 255;
+
+/// A `%[[:]flags][width[.precision]][doxXs]` conversion.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    width: usize,
+    conv: u8,
+}
+
+impl Spec {
+    /// Parse the conversion at the start of `s` (just after the `%`),
+    /// returning it and its length.
+    fn parse(s: &[u8]) -> (Spec, usize) {
+        let mut spec = Spec::default();
+        let mut i = usize::from(s.first() == Some(&b':'));
+        let mut precision = false;
+        while let Some(&c) = s.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                // Precision only matters for `%s`, which we don't support, so
+                // its digits are skipped.
+                b'0'..=b'9' if precision => {}
+                b'0' if spec.width == 0 => spec.zero = true,
+                b'0'..=b'9' => {
+                    spec.width = spec
+                        .width
+                        .saturating_mul(10)
+                        .saturating_add(usize::from(c - b'0'))
+                        .min(MAX_WIDTH);
+                }
+                b'.' => precision = true,
+                b'+' | b'#' | b' ' => {}
+                _ => break,
+            }
+            i += 1;
+        }
+        spec.conv = s.get(i).copied().unwrap_or(b'd');
+        (spec, i + 1)
+    }
+
+    fn write<W: Write + ?Sized>(&self, v: i32, out: &mut W) -> fmt::Result {
+        let w = self.width;
+        match (self.conv, self.left, self.zero) {
+            (b'o', true, _) => write!(out, "{v:<w$o}"),
+            (b'o', false, true) => write!(out, "{v:0w$o}"),
+            (b'o', false, false) => write!(out, "{v:w$o}"),
+            (b'x', true, _) => write!(out, "{v:<w$x}"),
+            (b'x', false, true) => write!(out, "{v:0w$x}"),
+            (b'x', false, false) => write!(out, "{v:w$x}"),
+            (b'X', true, _) => write!(out, "{v:<w$X}"),
+            (b'X', false, true) => write!(out, "{v:0w$X}"),
+            (b'X', false, false) => write!(out, "{v:w$X}"),
+            (b'd' | b's', true, _) => write!(out, "{v:<w$}"),
+            (b'd' | b's', false, true) => write!(out, "{v:0w$}"),
+            (b'd' | b's', false, false) => write!(out, "{v:w$}"),
+            // Not a conversion we know, so ignore it.
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::String;
+
+    const XTERM: &[u8] = include_bytes!("../testdata/terminfo/xterm");
+    const XTERM_256: &[u8] = include_bytes!("../testdata/terminfo/xterm-256color");
+    const XTERM_DIRECT: &[u8] = include_bytes!("../testdata/terminfo/xterm-direct");
+    const TC: &[u8] = include_bytes!("../testdata/terminfo/termpal-tc");
+
+    fn expand(t: Template<'_>, params: &[i32]) -> String {
+        let mut s = String::new();
+        t.expand(params, &mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_parse() {
+        // The legacy format, with 16-bit numbers.
+        let xterm = Terminfo::parse(XTERM).unwrap();
+        assert_eq!(xterm.names().next(), Some("xterm"));
+        assert_eq!((xterm.colors(), xterm.pairs()), (Some(8), Some(64)));
+        assert!(!xterm.truecolor());
+        assert!(xterm.has_extended("XT"));
+        assert_eq!(
+            xterm.setaf().map(|t| t.as_bytes()),
+            Some(&b"\x1b[3%p1%dm"[..])
+        );
+
+        let xterm256 = Terminfo::parse(XTERM_256).unwrap();
+        assert_eq!(
+            (xterm256.colors(), xterm256.pairs()),
+            (Some(256), Some(0x10000))
+        );
+        assert!(!xterm256.truecolor());
+
+        let direct = Terminfo::parse(XTERM_DIRECT).unwrap();
+        assert_eq!(direct.colors(), Some(0x1000000));
+        assert!(direct.truecolor());
+        assert!(direct.has_extended("RGB"));
+
+        let tc = Terminfo::parse(TC).unwrap();
+        assert_eq!(
+            tc.names().collect::<std::vec::Vec<_>>(),
+            ["termpal-tc", "test entry with Tc"]
+        );
+        assert_eq!((tc.colors(), tc.pairs()), (Some(256), Some(32767)));
+        assert!(tc.truecolor());
+        assert!(tc.has_extended("U8") && tc.has_extended("Ss") && tc.has_extended("Se"));
+        assert!(!tc.has_extended("RGB") && !tc.has_extended("Tc2"));
+
+        assert_eq!(Terminfo::parse(b"").unwrap_err(), Error::Truncated);
+        assert_eq!(Terminfo::parse(b"\x1e\x02").unwrap_err(), Error::Truncated);
+        assert_eq!(Terminfo::parse(b"hello").unwrap_err(), Error::BadMagic);
+        assert_eq!(
+            Terminfo::parse(&XTERM_256[..XTERM_256.len() / 2]).unwrap_err(),
+            Error::Truncated
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let xterm256 = Terminfo::parse(XTERM_256).unwrap();
+        let setaf = xterm256.setaf().unwrap();
+        let setab = xterm256.setab().unwrap();
+        assert_eq!(expand(setaf, &[1]), "\x1b[31m");
+        assert_eq!(expand(setaf, &[9]), "\x1b[91m");
+        assert_eq!(expand(setaf, &[200]), "\x1b[38;5;200m");
+        assert_eq!(expand(setab, &[7]), "\x1b[47m");
+        assert_eq!(expand(setab, &[15]), "\x1b[107m");
+
+        let direct = Terminfo::parse(XTERM_DIRECT).unwrap();
+        let setaf = direct.setaf().unwrap();
+        assert_eq!(expand(setaf, &[3]), "\x1b[33m");
+        assert_eq!(expand(setaf, &[0x12ab34]), "\x1b[38:2::18:171:52m");
+
+        let t = |s: &'static [u8], p: &[i32]| expand(Template(s), p);
+        assert_eq!(t(b"%p1%p2%+%d,%p2%02x,%p1%:-3d|", &[5, 10]), "15,0a,5  |");
+        assert_eq!(t(b"%i%p1%d;%p2%d%%", &[0, 0]), "1;1%");
+        assert_eq!(t(b"%i%p1%d", &[i32::MAX]), "-2147483648");
+        assert_eq!(t(b"%'A'%c%{66}%c%p1%Pa%ga%ga%*%d", &[7]), "AB49");
+        assert_eq!(
+            t(b"%?%p1%{1}%=%tone%e%p1%{2}%=%ttwo%eother%;.", &[2]),
+            "two."
+        );
+        assert_eq!(t(b"%?%p1%t%?%p2%ta%eb%;%ec%;", &[1, 0]), "b");
+        assert_eq!(t(b"%?%p1%t%?%p2%ta%eb%;%ec%;", &[0, 1]), "c");
+        // Malformed, but shouldn't panic.
+        assert_eq!(t(b"%d%+%{12", &[]), "0");
+        assert_eq!(t(b"x%", &[]), "x");
+        assert_eq!(t(b"%p1%70000d", &[7]).len(), MAX_WIDTH);
+        assert_eq!(t(b"%p1%99999999999999999999999d", &[7]).len(), MAX_WIDTH);
+        // The precision isn't part of the width.
+        assert_eq!(t(b"%p1%5.2d", &[7]), "    7");
+    }
+}
@@ -0,0 +1,253 @@
+//! Retuning a 256-color terminal's palette to show a theme's exact colors.
+//!
+//! Most terminals that only do 256 colors still let you change what those
+//! colors are, with OSC 4. So rather than settling for the nearest cube entry
+//! to each theme color, we can overwrite some entries with the theme colors,
+//! and then use those indices.
+//!
+//! Which entries to give up is the interesting part. The 16 named colors and
+//! the grey ramp are left alone, and within the 6×6×6 cube, the entries that
+//! go first are the ones closest (in Oklab) to some other entry, as they're
+//! the ones [`nearest_ansi256`](crate::nearest_ansi256) can best do without.
+//! Theme colors that are already exactly in the palette just use that entry.
+//!
+//! ```
+//! use termpal::theme::Retune;
+//!
+//! let theme = [(0x28, 0x2c, 0x34), (0xe0, 0x6c, 0x75), (0x98, 0xc3, 0x79)];
+//! let retune = Retune::new(&theme);
+//!
+//! let mut setup = String::new();
+//! retune.write_set(&mut setup).unwrap();
+//! print!("{setup}");
+//! for (i, index) in retune.indices().iter().enumerate() {
+//!     println!("\x1b[38;5;{index}mtheme color {i}\x1b[0m");
+//! }
+//!
+//! // Put things back how they were before exiting.
+//! let mut teardown = String::new();
+//! retune.write_reset(&mut teardown).unwrap();
+//! print!("{teardown}");
+//! ```
+use crate::imp::oklab::OkLab;
+use crate::imp::tab;
+use crate::palette::Palette;
+use core::fmt;
+
+type Rgb = (u8, u8, u8);
+
+/// The number of entries in the color cube, which is how many theme colors a
+/// [`Retune`] can hold.
+pub const MAX_COLORS: usize = 216;
+
+/// A set of palette changes, and which index each theme color ended up at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Retune {
+    /// The index for each theme color.
+    indices: [u8; MAX_COLORS],
+    len: usize,
+    /// The entries being overwritten, and their new colors.
+    changes: [(u8, Rgb); MAX_COLORS],
+    num_changes: usize,
+}
+
+impl Retune {
+    /// Work out which palette entries to overwrite to show `theme`.
+    ///
+    /// # Panics
+    ///
+    /// If `theme` has more than [`MAX_COLORS`] colors.
+    #[track_caller]
+    pub fn new(theme: &[Rgb]) -> Self {
+        assert!(
+            theme.len() <= MAX_COLORS,
+            "too many colors for a theme: {}",
+            theme.len(),
+        );
+        let mut labs = [OkLab::from_srgb8(0, 0, 0); 240];
+        for (lab, &(r, g, b)) in labs.iter_mut().zip(tab::ANSI256_RGB.iter()) {
+            *lab = OkLab::from_srgb8(r, g, b);
+        }
+
+        // First, reuse whatever is already in the palette, and make sure those
+        // entries don't get picked to be overwritten.
+        let mut out = Self {
+            indices: [0; MAX_COLORS],
+            len: theme.len(),
+            changes: [(0, (0, 0, 0)); MAX_COLORS],
+            num_changes: 0,
+        };
+        let mut reserved = [false; 240];
+        let mut needed = [false; MAX_COLORS];
+        for (i, &(r, g, b)) in theme.iter().enumerate() {
+            match tab::get_exact_color256(r, g, b) {
+                Some(index) => {
+                    out.indices[i] = index;
+                    reserved[index as usize - 16] = true;
+                }
+                // Only the first occurrence of each color needs a slot.
+                None => needed[i] = !theme[..i].contains(&(r, g, b)),
+            }
+        }
+
+        // Then pick the cube entries to give up, one at a time, as each one
+        // that goes changes how redundant its neighbors are.
+        let count = needed.iter().filter(|&&n| n).count();
+        let mut alive = [true; 240];
+        let mut slots = [0usize; MAX_COLORS];
+        for slot in &mut slots[..count] {
+            let best = (0..216)
+                .filter(|&c| alive[c] && !reserved[c])
+                .map(|c| {
+                    let nearest = (0..240)
+                        .filter(|&o| o != c && alive[o])
+                        .map(|o| labs[c].dist_sq(&labs[o]))
+                        .fold(f32::MAX, f32::min);
+                    (c, nearest)
+                })
+                .fold((0, f32::MAX), |a, b| if b.1 < a.1 { b } else { a });
+            alive[best.0] = false;
+            *slot = best.0;
+        }
+
+        // Finally, give each theme color whichever of those entries is
+        // nearest to it, so that things still look roughly right on terminals
+        // that ignore OSC 4.
+        let mut taken = [false; MAX_COLORS];
+        for (i, &(r, g, b)) in theme.iter().enumerate() {
+            if !needed[i] {
+                continue;
+            }
+            let lab = OkLab::from_srgb8(r, g, b);
+            let j = (0..count)
+                .filter(|&j| !taken[j])
+                .min_by(|&x, &y| {
+                    let dx = lab.dist_sq(&labs[slots[x]]);
+                    let dy = lab.dist_sq(&labs[slots[y]]);
+                    dx.total_cmp(&dy)
+                })
+                .expect("picked a slot for each needed color");
+            taken[j] = true;
+            let index = slots[j] as u8 + 16;
+            out.indices[i] = index;
+            out.changes[out.num_changes] = (index, (r, g, b));
+            out.num_changes += 1;
+        }
+        // Now fill in the repeated colors.
+        for i in 0..theme.len() {
+            if let Some(first) = theme[..i].iter().position(|&c| c == theme[i]) {
+                out.indices[i] = out.indices[first];
+            }
+        }
+        out.changes[..out.num_changes].sort_unstable_by_key(|&(index, _)| index);
+        out
+    }
+
+    /// The palette index to use for each theme color, in the same order as
+    /// the theme.
+    #[inline]
+    pub fn indices(&self) -> &[u8] {
+        &self.indices[..self.len]
+    }
+
+    /// The palette entries being overwritten, and their new colors, sorted by
+    /// index.
+    #[inline]
+    pub fn changes(&self) -> &[(u8, Rgb)] {
+        &self.changes[..self.num_changes]
+    }
+
+    /// The full 256-color palette after the changes, assuming it was the
+    /// xterm default beforehand.
+    ///
+    /// This can be passed to [`Rewriter::palette`](crate::rewrite::Rewriter::palette)
+    /// to upgrade the retuned indices to the theme's true colors.
+    pub fn palette(&self) -> Palette {
+        let mut p = Palette::new();
+        for i in 0..=255 {
+            p.push(crate::ansi256_to_rgb(i));
+        }
+        for &(index, color) in self.changes() {
+            p.set(index, color);
+        }
+        p
+    }
+
+    /// Write the OSC 4 sequences that make the changes.
+    pub fn write_set<W: fmt::Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        for &(index, (r, g, b)) in self.changes() {
+            write!(out, "\x1b]4;{index};rgb:{r:02x}/{g:02x}/{b:02x}\x07")?;
+        }
+        Ok(())
+    }
+
+    /// Write the OSC 104 sequence that puts the changed entries back to the
+    /// terminal's defaults. Nothing is written if there aren't any changes,
+    /// as an OSC 104 without indices resets the whole palette.
+    pub fn write_reset<W: fmt::Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        if self.num_changes == 0 {
+            return Ok(());
+        }
+        out.write_str("\x1b]104")?;
+        for &(index, _) in self.changes() {
+            write!(out, ";{index}")?;
+        }
+        out.write_char('\x07')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn test_retune() {
+        let theme = [
+            (0x28, 0x2c, 0x34),
+            (0xff, 0x00, 0x00),
+            (0xe0, 0x6c, 0x75),
+            (0x28, 0x2c, 0x34),
+            (0x98, 0xc3, 0x79),
+        ];
+        let retune = Retune::new(&theme);
+        let indices = retune.indices();
+        assert_eq!(indices.len(), 5);
+        // Exact matches and repeats don't need new entries.
+        assert_eq!(indices[1], 196);
+        assert_eq!(indices[3], indices[0]);
+        assert_eq!(retune.changes().len(), 3);
+        for (&index, &color) in indices.iter().zip(&theme) {
+            assert!((16..232).contains(&index), "{index}");
+            assert_eq!(retune.palette().get(index), Some(color));
+        }
+        // Every changed entry is distinct, and none is the exact match.
+        let mut changed: std::vec::Vec<u8> = retune.changes().iter().map(|c| c.0).collect();
+        changed.dedup();
+        assert_eq!(changed.len(), 3);
+        assert!(!changed.contains(&196));
+
+        let mut set = String::new();
+        retune.write_set(&mut set).unwrap();
+        let (a, b, c) = (changed[0], changed[1], changed[2]);
+        assert_eq!(set.matches("\x1b]4;").count(), 3);
+        assert!(set.contains(&std::format!("\x1b]4;{};rgb:28/2c/34\x07", indices[0])));
+        let mut reset = String::new();
+        retune.write_reset(&mut reset).unwrap();
+        assert_eq!(reset, std::format!("\x1b]104;{a};{b};{c}\x07"));
+
+        let mut empty = String::new();
+        Retune::new(&[(0, 0, 0)]).write_reset(&mut empty).unwrap();
+        assert_eq!(empty, "");
+    }
+
+    #[test]
+    fn test_full_cube() {
+        // Every cube entry can be overwritten, but no more.
+        let theme: std::vec::Vec<Rgb> = (0..MAX_COLORS as u8).map(|i| (i, 1, 2)).collect();
+        let retune = Retune::new(&theme);
+        let mut indices = retune.indices().to_vec();
+        indices.sort_unstable();
+        assert_eq!(indices, (16..232).collect::<std::vec::Vec<u8>>());
+    }
+}